@@ -0,0 +1,210 @@
+// HTTP 认证：API 客户端使用 Authorization: Bearer <token>，网页使用登录后下发的会话 Cookie
+// 密码以 Argon2 哈希（PHC 字符串）保存在 config.json；会话只保存在内存中，重启后需重新登录
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use argon2::password_hash::rand_core::OsRng;
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use axum::http::{header, HeaderMap};
+use rand::distributions::{Alphanumeric, DistString};
+
+use crate::config::AuthConfig;
+
+/// 会话 Cookie 名称
+pub const SESSION_COOKIE: &str = "sms_forwarder_session";
+
+/// 生成密码的 Argon2 哈希
+pub fn hash_password(password: &str) -> anyhow::Result<String> {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|h| h.to_string())
+        .map_err(|e| anyhow::anyhow!("hash password failed: {}", e))
+}
+
+/// 校验密码；哈希格式错误视为不匹配
+pub fn verify_password(password: &str, hash: &str) -> bool {
+    PasswordHash::new(hash)
+        .map(|h| Argon2::default().verify_password(password.as_bytes(), &h).is_ok())
+        .unwrap_or(false)
+}
+
+/// 随机字母数字串，用于初始密码与会话 ID
+pub fn random_secret(len: usize) -> String {
+    Alphanumeric.sample_string(&mut rand::thread_rng(), len)
+}
+
+/// 比较时间与内容无关，避免按耗时逐字节猜测 token
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// 已登录的会话：ID -> (用户名, 过期时间)
+#[derive(Clone, Default)]
+pub struct Sessions {
+    inner: Arc<Mutex<HashMap<String, (String, Instant)>>>,
+}
+
+impl Sessions {
+    /// 创建会话，返回会话 ID
+    pub fn create(&self, username: &str, ttl: Duration) -> String {
+        let id = random_secret(32);
+        let mut sessions = self.inner.lock().unwrap();
+        let now = Instant::now();
+        sessions.retain(|_, (_, expires)| *expires > now);
+        sessions.insert(id.clone(), (username.to_string(), now + ttl));
+        id
+    }
+
+    /// 有效会话对应的用户名
+    pub fn user(&self, id: &str) -> Option<String> {
+        let sessions = self.inner.lock().unwrap();
+        sessions
+            .get(id)
+            .filter(|(_, expires)| *expires > Instant::now())
+            .map(|(user, _)| user.clone())
+    }
+
+    pub fn remove(&self, id: &str) {
+        self.inner.lock().unwrap().remove(id);
+    }
+}
+
+/// 同一客户端对同一用户名连续失败超过这么多次后开始限速
+const FREE_LOGIN_ATTEMPTS: u32 = 3;
+/// 限速后第一次需等待的时间，之后每次失败翻倍
+const LOGIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_LOGIN_BACKOFF: Duration = Duration::from_secs(300);
+/// 最后一次失败超过这么久后清零
+const LOGIN_FAILURE_RESET: Duration = Duration::from_secs(900);
+/// 最多记录的（客户端, 用户名）数
+const MAX_TRACKED_LOGINS: usize = 1024;
+
+/// 登录失败限速的键：客户端地址与用户名。只按用户名限速时，任何人都能用错误密码让 admin 一直无法登录；
+/// 客户端地址未知（如 Unix 套接字）时为 None
+type LoginKey = (Option<IpAddr>, String);
+
+/// 登录失败限速：(客户端, 用户名) -> (连续失败次数, 最后一次失败时间)
+#[derive(Clone, Default)]
+pub struct LoginThrottle {
+    inner: Arc<Mutex<HashMap<LoginKey, (u32, Instant)>>>,
+}
+
+impl LoginThrottle {
+    /// 还需等待才能再次尝试时返回剩余时间
+    pub fn check(&self, client: Option<IpAddr>, username: &str) -> Option<Duration> {
+        let failures = self.inner.lock().unwrap();
+        let (count, last) = failures.get(&(client, username.to_string()))?;
+        if *count < FREE_LOGIN_ATTEMPTS {
+            return None;
+        }
+        let wait = LOGIN_BACKOFF
+            .saturating_mul(1 << (count - FREE_LOGIN_ATTEMPTS).min(16))
+            .min(MAX_LOGIN_BACKOFF);
+        (*last + wait).checked_duration_since(Instant::now())
+    }
+
+    pub fn failed(&self, client: Option<IpAddr>, username: &str) {
+        let mut failures = self.inner.lock().unwrap();
+        let now = Instant::now();
+        if failures.len() >= MAX_TRACKED_LOGINS {
+            // 先清理已清零和未到限速次数的记录，仍然太多时去掉最早的一条
+            failures.retain(|_, (count, last)| {
+                *count >= FREE_LOGIN_ATTEMPTS && now.duration_since(*last) < LOGIN_FAILURE_RESET
+            });
+            if failures.len() >= MAX_TRACKED_LOGINS {
+                let oldest = failures.iter().min_by_key(|(_, (_, last))| *last).map(|(k, _)| k.clone());
+                if let Some(k) = oldest {
+                    failures.remove(&k);
+                }
+            }
+        }
+        let entry = failures.entry((client, username.to_string())).or_insert((0, now));
+        if now.duration_since(entry.1) >= LOGIN_FAILURE_RESET {
+            entry.0 = 0;
+        }
+        *entry = (entry.0 + 1, now);
+    }
+
+    pub fn succeeded(&self, client: Option<IpAddr>, username: &str) {
+        self.inner.lock().unwrap().remove(&(client, username.to_string()));
+    }
+}
+
+/// 从 Cookie 头中取出会话 ID
+pub fn session_id(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(';'))
+        .filter_map(|kv| kv.trim().split_once('='))
+        .find(|(k, _)| *k == SESSION_COOKIE)
+        .map(|(_, v)| v.to_string())
+}
+
+/// 请求携带有效的 Bearer token 或会话 Cookie 时返回 true
+pub fn authorized(cfg: &AuthConfig, sessions: &Sessions, headers: &HeaderMap) -> bool {
+    let bearer = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(str::trim);
+    if let Some(token) = bearer {
+        if cfg
+            .api_tokens
+            .iter()
+            .any(|t| !t.is_empty() && constant_time_eq(t.as_bytes(), token.as_bytes()))
+        {
+            return true;
+        }
+    }
+    session_id(headers).is_some_and(|id| sessions.user(&id).is_some())
+}
+
+/// 校验用户名与密码
+pub fn login(cfg: &AuthConfig, username: &str, password: &str) -> bool {
+    cfg.users
+        .iter()
+        .any(|u| u.username == username && verify_password(password, &u.password_hash))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn throttle_is_per_client() {
+        let throttle = LoginThrottle::default();
+        let attacker: Option<IpAddr> = Some("203.0.113.7".parse().unwrap());
+        let owner: Option<IpAddr> = Some("192.168.1.10".parse().unwrap());
+        for _ in 0..FREE_LOGIN_ATTEMPTS {
+            assert!(throttle.check(attacker, "admin").is_none());
+            throttle.failed(attacker, "admin");
+        }
+        assert!(throttle.check(attacker, "admin").is_some());
+        // 其他客户端登录同一账号不受影响
+        assert!(throttle.check(owner, "admin").is_none());
+        assert!(throttle.check(None, "admin").is_none());
+        // 同一客户端换用户名也不受影响
+        assert!(throttle.check(attacker, "other").is_none());
+
+        throttle.succeeded(attacker, "admin");
+        assert!(throttle.check(attacker, "admin").is_none());
+    }
+
+    #[test]
+    fn backoff_grows_with_failures() {
+        let throttle = LoginThrottle::default();
+        let client: Option<IpAddr> = Some("203.0.113.7".parse().unwrap());
+        for _ in 0..FREE_LOGIN_ATTEMPTS + 3 {
+            throttle.failed(client, "admin");
+        }
+        let wait = throttle.check(client, "admin").unwrap();
+        assert!(wait > LOGIN_BACKOFF * 4 && wait <= LOGIN_BACKOFF * 8, "{:?}", wait);
+    }
+}
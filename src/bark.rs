@@ -1,17 +1,22 @@
 // 加解密方式与 Bark 文档一致: https://bark.day.app/#/encryption
-// 整包 JSON 使用 AES-128-CBC 加密，Base64 后以 POST 表单 ciphertext + iv 提交
+// 整包 JSON 使用 AES（CBC/ECB/GCM，128/192/256 位）加密，Base64 后以 POST 表单 ciphertext + iv 提交
+// IV 每次推送随机生成，随 iv 字段一起发送；分组、图标、跳转 URL 等附加参数同样写在加密 JSON 中
+// 一个渠道可配置多台设备，每台设备可使用各自的 AES key/模式
 
-use aes::Aes128;
+use aes::{Aes128, Aes192, Aes256};
+use aes_gcm::aead::{consts::U12, Aead};
+use aes_gcm::AesGcm;
 use base64::{engine::general_purpose, Engine};
-use cbc::Encryptor;
-use cipher::{block_padding::Pkcs7, BlockEncryptMut, KeyIvInit};
+use cipher::{
+    block_padding::Pkcs7, BlockCipher, BlockEncrypt, BlockEncryptMut, KeyInit, KeyIvInit,
+};
+use async_trait::async_trait;
 use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
-use reqwest::Client;
+use rand::distributions::{Alphanumeric, DistString};
 use serde::Serialize;
 
-use crate::config::Config;
-
-type Aes128CbcEnc = Encryptor<Aes128>;
+use crate::config::{AesMode, BarkConfig, BarkDevice, BarkOptions};
+use crate::notify::{self, Level, Notification, Notifier};
 
 /// 与 Bark 服务端约定的推送 payload 结构
 #[derive(Serialize)]
@@ -27,70 +32,318 @@ struct BarkPayload {
     volume: Option<u8>,
     #[serde(skip_serializing_if = "Option::is_none")]
     call: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    group: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    icon: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    copy: Option<String>,
+    #[serde(rename = "autoCopy", skip_serializing_if = "Option::is_none")]
+    auto_copy: Option<String>,
+    #[serde(rename = "isArchive", skip_serializing_if = "Option::is_none")]
+    is_archive: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    badge: Option<u32>,
+}
+
+impl BarkPayload {
+    /// 由通知与合并后的附加参数生成 payload，字符串参数中的占位符在此替换
+    fn new(n: &Notification, opts: &BarkOptions) -> Self {
+        let render = |s: &Option<String>| {
+            s.as_ref()
+                .map(|s| s.replace("{{sender}}", &n.sender).replace("{{title}}", &n.title))
+                .filter(|s| !s.is_empty())
+        };
+        // Bark 的布尔参数以 "1"/"0" 表示
+        let flag = |b: Option<bool>| b.map(|b| if b { "1" } else { "0" }.to_string());
+        Self {
+            body: n.body.clone(),
+            title: if n.title.is_empty() {
+                None
+            } else {
+                Some(n.title.clone())
+            },
+            sound: Some(render(&opts.sound).unwrap_or_else(|| "birdsong".to_string())),
+            level: match (n.emergency, n.level) {
+                (true, _) | (_, Level::Critical) => Some("critical".to_string()),
+                (_, Level::Active) => None,
+                (_, Level::Passive) => Some("passive".to_string()),
+                (_, Level::TimeSensitive) => Some("timeSensitive".to_string()),
+            },
+            volume: if n.emergency { Some(5) } else { None },
+            call: if n.emergency { Some(1) } else { None },
+            group: render(&opts.group),
+            icon: render(&opts.icon),
+            url: render(&opts.url),
+            copy: render(&opts.copy),
+            auto_copy: flag(opts.auto_copy),
+            is_archive: flag(opts.is_archive),
+            badge: opts.badge,
+        }
+    }
 }
 
-/// 使用 AES-128-CBC 加密明文，返回 Base64 密文（与 OpenSSL enc -aes-128-cbc 行为一致）
-fn encrypt(plaintext: &str, key: &[u8], iv: &[u8]) -> String {
-    let cipher = Aes128CbcEnc::new_from_slices(key, iv).expect("key/iv length");
-    let mut buf = vec![0u8; (plaintext.len() / 16 + 1) * 16];
-    buf[..plaintext.len()].copy_from_slice(plaintext.as_bytes());
-    let ciphertext = cipher
-        .encrypt_padded_mut::<Pkcs7>(&mut buf, plaintext.len())
-        .expect("encrypt");
-    general_purpose::STANDARD.encode(ciphertext)
+fn cbc_encrypt<C>(data: &[u8], key: &[u8], iv: &[u8]) -> anyhow::Result<Vec<u8>>
+where
+    C: BlockCipher + BlockEncryptMut + KeyInit,
+{
+    let cipher = cbc::Encryptor::<C>::new_from_slices(key, iv)
+        .map_err(|_| anyhow::anyhow!("invalid AES key/IV length"))?;
+    Ok(cipher.encrypt_padded_vec_mut::<Pkcs7>(data))
 }
 
-pub async fn push(
-    cfg: &Config,
-    title: &str,
-    body: &str,
-    emergency: bool,
-) -> anyhow::Result<()> {
-    let key = cfg.aes_key.as_bytes();
-    let iv = cfg.aes_iv.as_bytes();
-    if key.len() != 16 || iv.len() != 16 {
-        anyhow::bail!("aes_key 与 aes_iv 必须均为 16 字节");
+fn ecb_encrypt<C>(data: &[u8], key: &[u8]) -> anyhow::Result<Vec<u8>>
+where
+    C: BlockCipher + BlockEncryptMut + KeyInit,
+{
+    let cipher = ecb::Encryptor::<C>::new_from_slice(key)
+        .map_err(|_| anyhow::anyhow!("invalid AES key length"))?;
+    Ok(cipher.encrypt_padded_vec_mut::<Pkcs7>(data))
+}
+
+/// GCM 输出为密文后接 16 字节认证标签
+fn gcm_encrypt<C>(data: &[u8], key: &[u8], iv: &[u8]) -> anyhow::Result<Vec<u8>>
+where
+    C: BlockCipher<BlockSize = cipher::consts::U16> + BlockEncrypt + KeyInit,
+{
+    let cipher = AesGcm::<C, U12>::new_from_slice(key)
+        .map_err(|_| anyhow::anyhow!("invalid AES key length"))?;
+    if iv.len() != 12 {
+        anyhow::bail!("invalid AES-GCM IV length");
     }
+    cipher
+        .encrypt(iv.into(), data)
+        .map_err(|_| anyhow::anyhow!("AES-GCM encryption failed"))
+}
 
-    let payload = BarkPayload {
-        body: body.to_string(),
-        title: if title.is_empty() {
-            None
-        } else {
-            Some(title.to_string())
-        },
-        sound: Some("birdsong".to_string()),
-        level: if emergency {
-            Some("critical".to_string())
-        } else {
-            None
-        },
-        volume: if emergency { Some(5) } else { None },
-        call: if emergency { Some(1) } else { None },
+/// 按模式与密钥长度选择 AES 变体加密明文，返回 Base64 密文（CBC/ECB 与 OpenSSL enc 行为一致）
+fn encrypt(plaintext: &str, mode: AesMode, key: &[u8], iv: &[u8]) -> anyhow::Result<String> {
+    let data = plaintext.as_bytes();
+    let ciphertext = match (mode, key.len()) {
+        (AesMode::Cbc, 16) => cbc_encrypt::<Aes128>(data, key, iv)?,
+        (AesMode::Cbc, 24) => cbc_encrypt::<Aes192>(data, key, iv)?,
+        (AesMode::Cbc, 32) => cbc_encrypt::<Aes256>(data, key, iv)?,
+        (AesMode::Ecb, 16) => ecb_encrypt::<Aes128>(data, key)?,
+        (AesMode::Ecb, 24) => ecb_encrypt::<Aes192>(data, key)?,
+        (AesMode::Ecb, 32) => ecb_encrypt::<Aes256>(data, key)?,
+        (AesMode::Gcm, 16) => gcm_encrypt::<Aes128>(data, key, iv)?,
+        (AesMode::Gcm, 24) => gcm_encrypt::<Aes192>(data, key, iv)?,
+        (AesMode::Gcm, 32) => gcm_encrypt::<Aes256>(data, key, iv)?,
+        (_, n) => anyhow::bail!("aes_key 长度为 {} 字节，应为 16、24 或 32 字节", n),
     };
+    Ok(general_purpose::STANDARD.encode(ciphertext))
+}
 
-    let json = serde_json::to_string(&payload)?;
-    let ciphertext = encrypt(&json, key, iv);
+/// 随机生成 IV：Bark 客户端按字符串读取 iv，故只用字母与数字；CBC 为 16 字节，GCM 为 12 字节，ECB 不需要
+fn random_iv(mode: AesMode) -> Option<String> {
+    let len = match mode {
+        AesMode::Cbc => 16,
+        AesMode::Gcm => 12,
+        AesMode::Ecb => return None,
+    };
+    Some(Alphanumeric.sample_string(&mut rand::thread_rng(), len))
+}
 
-    let url = format!("https://api.day.app/{}", cfg.bark_key);
-    let body = format!(
-        "ciphertext={}&iv={}",
-        utf8_percent_encode(&ciphertext, NON_ALPHANUMERIC),
-        utf8_percent_encode(&cfg.aes_iv, NON_ALPHANUMERIC)
+/// 加密 payload JSON，生成 POST 表单：ciphertext=<Base64 密文>[&iv=<IV>]；ECB 不带 iv
+fn form_body(json: &str, mode: AesMode, key: &[u8], iv: Option<&str>) -> anyhow::Result<String> {
+    let ciphertext = encrypt(json, mode, key, iv.unwrap_or_default().as_bytes())?;
+    let mut body = format!(
+        "ciphertext={}",
+        utf8_percent_encode(&ciphertext, NON_ALPHANUMERIC)
     );
+    if let Some(iv) = iv {
+        body.push_str("&iv=");
+        body.push_str(iv);
+    }
+    Ok(body)
+}
 
-    tracing::debug!(title = %title, emergency = emergency, "pushing to Bark (encrypted)");
-    let res = Client::new()
+/// Bark 渠道
+pub struct Bark {
+    cfg: BarkConfig,
+}
+
+impl Bark {
+    pub fn new(cfg: BarkConfig) -> Self {
+        Self { cfg }
+    }
+}
+
+#[async_trait]
+impl Notifier for Bark {
+    /// 推送到全部设备；任一设备失败则整体返回 Err
+    async fn send(&self, n: &Notification) -> anyhow::Result<()> {
+        self.send_remaining(n, &mut Vec::new()).await
+    }
+
+    /// 推送到尚未成功的设备（按 device key 记录）；任一设备失败则返回 Err，发件箱重试时只推送失败的设备
+    async fn send_remaining(&self, n: &Notification, delivered: &mut Vec<String>) -> anyhow::Result<()> {
+        if self.cfg.bark_key.is_empty() {
+            anyhow::bail!("no Bark device key configured");
+        }
+        let payload = BarkPayload::new(n, &self.cfg.bark_options.merge(&n.bark));
+        let mut failed = Vec::new();
+        for device in &self.cfg.bark_key {
+            if delivered.contains(&device.key) {
+                continue;
+            }
+            match push(&self.cfg, device, &payload).await {
+                Ok(()) => delivered.push(device.key.clone()),
+                Err(e) => failed.push(format!("{}: {}", mask(&device.key), e)),
+            }
+        }
+        if !failed.is_empty() {
+            anyhow::bail!("Bark push failed for {}", failed.join("; "));
+        }
+        Ok(())
+    }
+}
+
+/// 日志与错误中只显示 key 的前 4 位
+fn mask(key: &str) -> String {
+    format!("{}***", key.chars().take(4).collect::<String>())
+}
+
+async fn push(
+    cfg: &BarkConfig,
+    device: &BarkDevice,
+    payload: &BarkPayload,
+) -> anyhow::Result<()> {
+    let aes_key = device.aes_key.as_deref().unwrap_or(&cfg.aes_key);
+    let mode = device.aes_mode.unwrap_or(cfg.aes_mode);
+    let json = serde_json::to_string(payload)?;
+    let body = form_body(&json, mode, aes_key.as_bytes(), random_iv(mode).as_deref())?;
+
+    let url = format!("{}/{}", cfg.bark_server.trim_end_matches('/'), device.key);
+
+    tracing::debug!(
+        device = %mask(&device.key),
+        title = ?payload.title,
+        level = ?payload.level,
+        "pushing to Bark (encrypted)"
+    );
+    // URL 中带有完整的 device key，错误信息会写入发件箱与短信历史，去掉 URL 后再返回
+    let res = notify::http_client()
         .post(&url)
         .header("Content-Type", "application/x-www-form-urlencoded")
         .body(body)
         .send()
-        .await;
+        .await
+        .map_err(reqwest::Error::without_url);
     match &res {
         Ok(r) => tracing::debug!(status = %r.status(), "Bark request completed"),
-        Err(e) => tracing::error!(err = %e, "Bark request failed"),
+        Err(e) => tracing::error!(device = %mask(&device.key), err = %e, "Bark request failed"),
     }
-    res?;
+    // 非 2xx 同样视为失败，调用方据此决定是否保留短信重试
+    res?.error_for_status().map_err(reqwest::Error::without_url)?;
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &str = "SecretDeviceKey123";
+
+    fn bark(server: &str) -> Bark {
+        Bark::new(BarkConfig {
+            bark_server: server.to_string(),
+            bark_key: vec![BarkDevice {
+                key: KEY.to_string(),
+                aes_key: None,
+                aes_mode: None,
+            }],
+            aes_key: "0123456789abcdef".to_string(),
+            ..BarkConfig::default()
+        })
+    }
+
+    fn notification() -> Notification {
+        Notification {
+            title: "SMS from 10086".to_string(),
+            body: "hello".to_string(),
+            emergency: false,
+            level: Level::Active,
+            sender: "10086".to_string(),
+            timestamp: None,
+            indices: Vec::new(),
+            bark: BarkOptions::default(),
+            history_id: None,
+        }
+    }
+
+    const PLAINTEXT: &str = r#"{"body":"test","sound":"birdsong"}"#;
+    const KEY_16: &[u8] = b"0123456789abcdef";
+    const KEY_24: &[u8] = b"0123456789abcdef01234567";
+    const KEY_32: &[u8] = b"0123456789abcdef0123456789abcdef";
+    const CBC_IV: &str = "fedcba9876543210";
+    const GCM_IV: &str = "abcdefghijkl";
+
+    /// 期望值由 Python cryptography 独立计算，与 Bark 客户端解密方式一致
+    #[test]
+    fn encrypt_known_answers() {
+        let cases = [
+            (AesMode::Cbc, KEY_16, CBC_IV, "djnluW6Zz1BRjbsR5lrB8VhnFCaBQaMq8MnM7atlNC/vcJNR4/MJYbIPWfAjBcIs"),
+            (AesMode::Cbc, KEY_24, CBC_IV, "T+yN5VFHZXE/Y32Z6LZPisioCY2jP+gleRUyTkB6ur3mmSAhclsspv9f8iIMlD7Q"),
+            (AesMode::Cbc, KEY_32, CBC_IV, "fw4eYjtMCg3Uj8aDLywjT9tQziOIvhCwHgSRV+dhPe7VKifBIhVYKNRgYX8ERsl9"),
+            (AesMode::Ecb, KEY_16, "", "miHU+QYAz/7V4j5lavnSC/HVVsJq53s81S92q6nustIO/qSKAJHapZWjp4D+E70O"),
+            (AesMode::Ecb, KEY_24, "", "u/avCm5qRQqxlfASbd2uCvI40DIn7djpeDYrgmdfy5NzGWt05i1omepdc23lTl9s"),
+            (AesMode::Ecb, KEY_32, "", "PU1VT1U7WhiCUOg9UFo4irq11dlhHXWfyQh4n3OuhtR1erUm9hzZQGgkC27knwOs"),
+            (AesMode::Gcm, KEY_16, GCM_IV, "nvAe2jfce597RNFJghzzK3l6mYlc2Qlw5pW/HCE4JtY+Y7/Z9PnWnDoYTRHgk5AtChg="),
+            (AesMode::Gcm, KEY_24, GCM_IV, "g46R6t6Uaas//3OLaLMBEvHkyP8NoR+0R8chIewiHQLwSU18SJLl3tn1ryOpSOpcVxQ="),
+            (AesMode::Gcm, KEY_32, GCM_IV, "Eu6r0K+cT+lBaJdifrt0HS9eznHdolV5fNdM+KEzoI1OtQPIYTcbzjZXYisbC/8/rAc="),
+        ];
+        for (mode, key, iv, expected) in cases {
+            assert_eq!(
+                encrypt(PLAINTEXT, mode, key, iv.as_bytes()).unwrap(),
+                expected,
+                "AES-{} iv {:?}",
+                key.len() * 8,
+                iv
+            );
+        }
+    }
+
+    #[test]
+    fn form_body_carries_the_iv() {
+        let body = form_body(PLAINTEXT, AesMode::Cbc, KEY_16, Some(CBC_IV)).unwrap();
+        assert_eq!(
+            body,
+            "ciphertext=djnluW6Zz1BRjbsR5lrB8VhnFCaBQaMq8MnM7atlNC%2FvcJNR4%2FMJYbIPWfAjBcIs&iv=fedcba9876543210"
+        );
+        let body = form_body(PLAINTEXT, AesMode::Ecb, KEY_16, None).unwrap();
+        assert!(!body.contains("iv="));
+    }
+
+    #[test]
+    fn random_iv_matches_mode() {
+        for (mode, len) in [(AesMode::Cbc, Some(16)), (AesMode::Gcm, Some(12)), (AesMode::Ecb, None)] {
+            let iv = random_iv(mode);
+            assert_eq!(iv.as_ref().map(String::len), len);
+            assert!(iv.unwrap_or_default().bytes().all(|b| b.is_ascii_alphanumeric()));
+        }
+        assert_ne!(random_iv(AesMode::Cbc), random_iv(AesMode::Cbc));
+    }
+
+    #[test]
+    fn invalid_key_length_is_rejected() {
+        assert!(encrypt(PLAINTEXT, AesMode::Cbc, b"short", CBC_IV.as_bytes()).is_err());
+        assert!(encrypt(PLAINTEXT, AesMode::Gcm, KEY_16, b"wrong iv").is_err());
+    }
+
+    #[tokio::test]
+    async fn errors_do_not_contain_device_key() {
+        let server = notify::http_stub("500 Internal Server Error", "").await;
+        let err = bark(&server).send(&notification()).await.unwrap_err().to_string();
+        assert!(err.contains("500"), "{}", err);
+        assert!(!err.contains(KEY), "{}", err);
+
+        // 连接失败
+        let err = bark("http://127.0.0.1:1").send(&notification()).await.unwrap_err().to_string();
+        assert!(err.contains("Secr***"), "{}", err);
+        assert!(!err.contains(KEY), "{}", err);
+    }
+}
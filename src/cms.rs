@@ -0,0 +1,166 @@
+// +CMS ERROR 结果码（3GPP TS 27.005 3.2.5；0-255 为 TS 24.011 / 23.040 中网络与短信中心给出的原因值）
+// 将错误码转换为可读原因，并区分可重试的临时错误与号码/内容被拒绝的永久错误
+
+/// 错误码对应的原因说明；未知错误码返回 None
+pub fn reason(code: u16) -> Option<&'static str> {
+    let text = match code {
+        1 => "unassigned (unallocated) number",
+        8 => "operator determined barring",
+        10 => "call barred",
+        21 => "short message transfer rejected",
+        27 => "destination out of service",
+        28 => "unidentified subscriber",
+        29 => "facility rejected",
+        30 => "unknown subscriber",
+        38 => "network out of order",
+        41 => "temporary failure",
+        42 => "congestion",
+        47 => "resources unavailable, unspecified",
+        50 => "requested facility not subscribed",
+        69 => "requested facility not implemented",
+        81 => "invalid short message transfer reference value",
+        95 => "invalid message, unspecified",
+        96 => "invalid mandatory information",
+        97 => "message type non-existent or not implemented",
+        98 => "message not compatible with short message protocol state",
+        99 => "information element non-existent or not implemented",
+        111 => "protocol error, unspecified",
+        127 => "interworking, unspecified",
+        128 => "telematic interworking not supported",
+        129 => "short message type 0 not supported",
+        130 => "cannot replace short message",
+        143 => "unspecified TP-PID error",
+        144 => "data coding scheme (alphabet) not supported",
+        145 => "message class not supported",
+        159 => "unspecified TP-DCS error",
+        160 => "command cannot be actioned",
+        161 => "command unsupported",
+        175 => "unspecified TP-Command error",
+        176 => "TPDU not supported",
+        192 => "SC busy",
+        193 => "no SC subscription",
+        194 => "SC system failure",
+        195 => "invalid SME address",
+        196 => "destination SME barred",
+        197 => "SM rejected - duplicate SM",
+        198 => "TP-VPF not supported",
+        199 => "TP-VP not supported",
+        208 => "(U)SIM SMS storage full",
+        209 => "no SMS storage capability in (U)SIM",
+        210 => "error in MS",
+        211 => "memory capacity exceeded",
+        212 => "(U)SIM application toolkit busy",
+        213 => "(U)SIM data download error",
+        255 => "unspecified error cause",
+        300 => "ME failure",
+        301 => "SMS service of ME reserved",
+        302 => "operation not allowed",
+        303 => "operation not supported",
+        304 => "invalid PDU mode parameter",
+        305 => "invalid text mode parameter",
+        310 => "(U)SIM not inserted",
+        311 => "(U)SIM PIN required",
+        312 => "PH-(U)SIM PIN required",
+        313 => "(U)SIM failure",
+        314 => "(U)SIM busy",
+        315 => "(U)SIM wrong",
+        316 => "(U)SIM PUK required",
+        317 => "(U)SIM PIN2 required",
+        318 => "(U)SIM PUK2 required",
+        320 => "memory failure",
+        321 => "invalid memory index",
+        322 => "memory full",
+        330 => "SMSC address unknown",
+        331 => "no network service",
+        332 => "network timeout",
+        340 => "no +CNMA acknowledgement expected",
+        500 => "unknown error",
+        _ => return None,
+    };
+    Some(text)
+}
+
+/// 网络拥塞、无服务、SIM 忙等临时错误，稍后重发可能成功
+pub fn is_transient(code: u16) -> bool {
+    matches!(code, 27 | 38 | 41 | 42 | 47 | 192 | 212 | 314 | 331 | 332)
+}
+
+/// 号码无效、被拒收或短信内容不被接受，原样重发不会成功
+pub fn is_rejected(code: u16) -> bool {
+    matches!(
+        code,
+        1 | 8 | 10 | 21 | 28 | 29 | 30 | 50 | 95..=99 | 128..=130 | 143..=145 | 159 | 176 | 195..=199 | 304 | 305
+    )
+}
+
+/// SIM 未插入、需要 PIN 或短信中心号码未设置等，需要人工处理 modem
+pub fn is_unavailable(code: u16) -> bool {
+    // 314（SIM 忙）是临时错误，不在此列
+    matches!(code, 193 | 301 | 310..=313 | 315..=318 | 330)
+}
+
+/// 解析 "+CMS ERROR: <err>" 行：返回错误码（modem 以文字给出时为 None，需 AT+CMEE=2）与原因
+pub fn parse(line: &str) -> Option<(Option<u16>, String)> {
+    let err = line.trim().strip_prefix("+CMS ERROR:")?.trim();
+    Some(match err.parse::<u16>() {
+        Ok(code) => (
+            Some(code),
+            reason(code).map_or_else(|| format!("CMS error {}", code), str::to_string),
+        ),
+        Err(_) => (None, err.to_string()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numeric_network_error_is_transient() {
+        let (code, reason) = parse("+CMS ERROR: 38").unwrap();
+        assert_eq!(code, Some(38));
+        assert_eq!(reason, "network out of order");
+        assert!(is_transient(38));
+        assert!(!is_rejected(38));
+        assert!(!is_unavailable(38));
+    }
+
+    #[test]
+    fn text_mode_error_keeps_message() {
+        // AT+CMEE=2 时 modem 以文字给出原因
+        let (code, reason) = parse("  +CMS ERROR: SMSC address unknown\r").unwrap();
+        assert_eq!(code, None);
+        assert_eq!(reason, "SMSC address unknown");
+    }
+
+    #[test]
+    fn unknown_code_is_not_retried() {
+        let (code, reason) = parse("+CMS ERROR: 999").unwrap();
+        assert_eq!(code, Some(999));
+        assert_eq!(reason, "CMS error 999");
+        assert_eq!(super::reason(999), None);
+        assert!(!is_transient(999));
+        assert!(!is_rejected(999));
+        assert!(!is_unavailable(999));
+    }
+
+    #[test]
+    fn classification() {
+        assert!(is_transient(42) && is_transient(332));
+        assert!(is_rejected(1) && is_rejected(96) && is_rejected(195));
+        assert!(is_unavailable(310) && is_unavailable(330));
+        assert!(is_transient(314) && !is_unavailable(314));
+        // 各类之间互不重叠
+        for code in 0..=600 {
+            let classes = [is_transient(code), is_rejected(code), is_unavailable(code)];
+            assert!(classes.iter().filter(|c| **c).count() <= 1, "code {}", code);
+        }
+    }
+
+    #[test]
+    fn other_lines_are_not_cms_errors() {
+        assert_eq!(parse("OK"), None);
+        assert_eq!(parse("+CME ERROR: 10"), None);
+        assert_eq!(parse("ERROR"), None);
+    }
+}
@@ -0,0 +1,242 @@
+// 长短信重组：按发送方 + UDH 参考号归组分段，收齐后合并为一条；超时未收齐则带“不完整”标记输出
+
+use chrono::{DateTime, FixedOffset};
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
+use tokio::time::Instant;
+
+use crate::pdu::Concat;
+
+/// 重组完成（或超时）的一条消息
+pub struct Assembled {
+    pub number: String,
+    pub body: String,
+    /// 各分段在 SIM 上的存储索引，转发后需逐一删除
+    pub indices: Vec<u32>,
+    /// 第一个分段的服务中心时间戳
+    pub timestamp: Option<DateTime<FixedOffset>>,
+    pub complete: bool,
+    /// 各分段的原始内容（PDU 十六进制或文本模式正文），按序号排列
+    pub raw: Vec<String>,
+}
+
+#[derive(Clone, Hash, PartialEq, Eq)]
+struct Key {
+    number: String,
+    reference: u16,
+    total: u8,
+}
+
+struct Pending {
+    /// 序号 -> (存储索引, 分段正文, 原始内容)
+    parts: BTreeMap<u8, (Option<u32>, String, String)>,
+    timestamp: Option<DateTime<FixedOffset>>,
+    first_seen: Instant,
+}
+
+pub struct Reassembler {
+    pending: HashMap<Key, Pending>,
+    timeout: Duration,
+}
+
+impl Reassembler {
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            pending: HashMap::new(),
+            timeout,
+        }
+    }
+
+    /// 更新分段等待时间，对已在等待中的长短信同样生效
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// 放入一个分段；所有分段到齐时返回合并结果。
+    /// 同一分段重复放入（例如兜底轮询再次读到仍在 SIM 上的分段）只会覆盖，不会重复计数
+    pub fn push(
+        &mut self,
+        number: &str,
+        concat: Concat,
+        index: Option<u32>,
+        text: String,
+        raw: String,
+        timestamp: Option<DateTime<FixedOffset>>,
+    ) -> Option<Assembled> {
+        let key = Key {
+            number: number.to_string(),
+            reference: concat.reference,
+            total: concat.total,
+        };
+        let pending = self.pending.entry(key.clone()).or_insert_with(|| Pending {
+            parts: BTreeMap::new(),
+            timestamp: None,
+            first_seen: Instant::now(),
+        });
+        if concat.seq == 1 || pending.timestamp.is_none() {
+            pending.timestamp = timestamp.or(pending.timestamp);
+        }
+        pending.parts.insert(concat.seq, (index, text, raw));
+        if pending.parts.len() < concat.total as usize {
+            return None;
+        }
+        self.pending.remove(&key).map(|p| assemble(key, p, true))
+    }
+
+    /// 最早一组分段的超时时刻，用于决定等待 URC 的截止时间
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.pending
+            .values()
+            .map(|p| p.first_seen + self.timeout)
+            .min()
+    }
+
+    /// 取出所有已超时的分段组，按已收到的分段拼接并标记为不完整
+    pub fn take_expired(&mut self) -> Vec<Assembled> {
+        let now = Instant::now();
+        let expired: Vec<Key> = self
+            .pending
+            .iter()
+            .filter(|(_, p)| now >= p.first_seen + self.timeout)
+            .map(|(k, _)| k.clone())
+            .collect();
+        expired
+            .into_iter()
+            .filter_map(|k| {
+                let p = self.pending.remove(&k)?;
+                Some(assemble(k, p, false))
+            })
+            .collect()
+    }
+}
+
+fn assemble(key: Key, pending: Pending, complete: bool) -> Assembled {
+    let received = pending.parts.len();
+    let mut body = String::new();
+    let mut indices = Vec::new();
+    let mut raws = Vec::new();
+    let mut expected = 1u16;
+    for (seq, (index, text, raw)) in pending.parts {
+        if seq as u16 != expected {
+            // 中间缺失的分段以省略号占位
+            body.push('…');
+        }
+        body.push_str(&text);
+        indices.extend(index);
+        raws.push(raw);
+        expected = seq as u16 + 1;
+    }
+    if !complete {
+        if expected <= key.total as u16 {
+            body.push('…');
+        }
+        body.push_str(&format!(
+            "\n[长短信不完整：仅收到 {}/{} 段]",
+            received, key.total
+        ));
+    }
+    Assembled {
+        number: key.number,
+        body,
+        indices,
+        timestamp: pending.timestamp,
+        complete,
+        raw: raws,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn part(r: &mut Reassembler, reference: u16, total: u8, seq: u8, text: &str) -> Option<Assembled> {
+        let concat = Concat {
+            reference,
+            total,
+            seq,
+        };
+        r.push(
+            "+8613800138000",
+            concat,
+            Some(seq as u32 + 10),
+            text.to_string(),
+            format!("raw{}", seq),
+            None,
+        )
+    }
+
+    #[test]
+    fn out_of_order_segments_are_joined_in_order() {
+        let mut r = Reassembler::new(Duration::from_secs(60));
+        assert!(part(&mut r, 7, 3, 3, "c").is_none());
+        assert!(part(&mut r, 7, 3, 1, "a").is_none());
+        let msg = part(&mut r, 7, 3, 2, "b").unwrap();
+        assert!(msg.complete);
+        assert_eq!(msg.body, "abc");
+        assert_eq!(msg.indices, vec![11, 12, 13]);
+        assert_eq!(msg.raw, vec!["raw1", "raw2", "raw3"]);
+        assert!(r.next_deadline().is_none());
+    }
+
+    #[test]
+    fn duplicate_segment_is_not_counted_twice() {
+        let mut r = Reassembler::new(Duration::from_secs(60));
+        assert!(part(&mut r, 7, 2, 1, "a").is_none());
+        // 兜底轮询再次读到同一分段
+        assert!(part(&mut r, 7, 2, 1, "a").is_none());
+        let msg = part(&mut r, 7, 2, 2, "b").unwrap();
+        assert_eq!(msg.body, "ab");
+        assert_eq!(msg.indices, vec![11, 12]);
+    }
+
+    #[test]
+    fn mismatched_total_is_a_different_message() {
+        let mut r = Reassembler::new(Duration::from_secs(60));
+        assert!(part(&mut r, 7, 2, 1, "a").is_none());
+        // 同一参考号但分段数不同，不能凑成一条
+        assert!(part(&mut r, 7, 3, 2, "x").is_none());
+        assert!(r.take_expired().is_empty());
+        let msg = part(&mut r, 7, 2, 2, "b").unwrap();
+        assert_eq!(msg.body, "ab");
+        assert!(r.next_deadline().is_some());
+    }
+
+    #[test]
+    fn different_senders_are_kept_apart() {
+        let mut r = Reassembler::new(Duration::from_secs(60));
+        let concat = Concat {
+            reference: 7,
+            total: 2,
+            seq: 1,
+        };
+        assert!(r
+            .push("10086", concat, Some(1), "x".into(), String::new(), None)
+            .is_none());
+        assert!(part(&mut r, 7, 2, 2, "b").is_none());
+    }
+
+    #[test]
+    fn timeout_flags_group_incomplete() {
+        let mut r = Reassembler::new(Duration::from_secs(60));
+        assert!(part(&mut r, 7, 4, 2, "b").is_none());
+        assert!(part(&mut r, 7, 4, 4, "d").is_none());
+        assert!(r.take_expired().is_empty());
+        r.set_timeout(Duration::ZERO);
+        let expired = r.take_expired();
+        assert_eq!(expired.len(), 1);
+        let msg = &expired[0];
+        assert!(!msg.complete);
+        assert_eq!(msg.body, "…b…d\n[长短信不完整：仅收到 2/4 段]");
+        // 只删除收到的分段
+        assert_eq!(msg.indices, vec![12, 14]);
+        assert!(r.next_deadline().is_none());
+    }
+
+    #[test]
+    fn missing_tail_is_marked() {
+        let mut r = Reassembler::new(Duration::ZERO);
+        assert!(part(&mut r, 7, 3, 1, "a").is_none());
+        let msg = r.take_expired().pop().unwrap();
+        assert_eq!(msg.body, "a…\n[长短信不完整：仅收到 1/3 段]");
+    }
+}
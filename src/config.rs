@@ -1,5 +1,8 @@
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::BTreeMap;
 use std::fs;
+use std::sync::Arc;
+use tokio::sync::watch;
 
 fn default_modem_device() -> String {
     "/dev/ttyUSB2".to_string()
@@ -7,23 +10,489 @@ fn default_modem_device() -> String {
 fn default_baud_rate() -> u32 {
     115200
 }
+fn default_poll_interval_secs() -> u64 {
+    60
+}
+fn default_concat_timeout_secs() -> u64 {
+    120
+}
+fn default_bark_server() -> String {
+    "https://api.day.app".to_string()
+}
+fn default_telegram_api_base() -> String {
+    "https://api.telegram.org".to_string()
+}
+fn default_signature_header() -> String {
+    "X-Signature-256".to_string()
+}
+fn default_smtp_subject_template() -> String {
+    "SMS from {{sender}}".to_string()
+}
+fn default_true() -> bool {
+    true
+}
+fn default_outbox_max_age_secs() -> u64 {
+    3 * 24 * 3600
+}
+fn default_outbox_max_backoff_secs() -> u64 {
+    3600
+}
+fn default_history_retention_days() -> u32 {
+    90
+}
+fn default_send_rate_limit() -> u32 {
+    10
+}
+fn default_send_max_attempts() -> u32 {
+    5
+}
+fn default_send_max_age_secs() -> u64 {
+    3600
+}
+fn default_listen_addr() -> String {
+    "0.0.0.0".to_string()
+}
+fn default_listen_port() -> u16 {
+    10086
+}
+fn default_session_ttl_secs() -> u64 {
+    7 * 24 * 3600
+}
 
+/// 收短信所用的 AT+CMGF 模式
+#[derive(Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SmsMode {
+    /// 文本模式（AT+CMGF=1），由 modem 解码
+    #[default]
+    Text,
+    /// PDU 模式（AT+CMGF=0），自行解码 TP-DU
+    Pdu,
+}
+
+/// Bark 加密推送的 AES 工作模式，密钥长度 16/24/32 字节分别对应 AES-128/192/256
+#[derive(Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AesMode {
+    #[default]
+    Cbc,
+    Ecb,
+    Gcm,
+}
+
+/// 一台接收 Bark 推送的设备
 #[derive(Clone, Serialize, Deserialize)]
-pub struct Config {
-    pub bark_key: String,
+pub struct BarkDevice {
+    pub key: String,
+    /// 该设备单独的 AES key/模式，为空时使用渠道级的 aes_key/aes_mode
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aes_key: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aes_mode: Option<AesMode>,
+}
+
+/// bark_key 兼容旧格式的单个字符串，也可以是设备列表（元素为 key 字符串或 BarkDevice 对象）
+fn deserialize_bark_keys<'de, D: Deserializer<'de>>(d: D) -> Result<Vec<BarkDevice>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Device {
+        Key(String),
+        Full(BarkDevice),
+    }
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Keys {
+        One(String),
+        Many(Vec<Device>),
+    }
+    let device = |d: Device| match d {
+        Device::Key(key) => BarkDevice {
+            key,
+            aes_key: None,
+            aes_mode: None,
+        },
+        Device::Full(d) => d,
+    };
+    Ok(match Keys::deserialize(d)? {
+        Keys::One(key) if key.is_empty() => Vec::new(),
+        Keys::One(key) => vec![device(Device::Key(key))],
+        Keys::Many(list) => list.into_iter().map(device).collect(),
+    })
+}
+
+/// Bark 推送的附加参数，均写入加密 JSON；group/icon/url/copy 支持 {{sender}}、{{title}} 占位符
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BarkOptions {
+    /// 铃声，为空时使用 birdsong
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sound: Option<String>,
+    /// 通知分组，例如 "{{sender}}" 按发送方分组
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+    /// 自定义图标 URL
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub icon: Option<String>,
+    /// 点击通知时打开的 URL
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// 复制通知时使用的内容，为空时复制正文
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub copy: Option<String>,
+    /// 收到通知后自动复制
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub auto_copy: Option<bool>,
+    /// 是否保存到 Bark 历史记录
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub is_archive: Option<bool>,
+    /// App 角标数字
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub badge: Option<u32>,
+}
+
+impl BarkOptions {
+    /// 以 other 中已设置的参数覆盖自身
+    pub fn merge(&self, other: &BarkOptions) -> BarkOptions {
+        BarkOptions {
+            sound: other.sound.clone().or_else(|| self.sound.clone()),
+            group: other.group.clone().or_else(|| self.group.clone()),
+            icon: other.icon.clone().or_else(|| self.icon.clone()),
+            url: other.url.clone().or_else(|| self.url.clone()),
+            copy: other.copy.clone().or_else(|| self.copy.clone()),
+            auto_copy: other.auto_copy.or(self.auto_copy),
+            is_archive: other.is_archive.or(self.is_archive),
+            badge: other.badge.or(self.badge),
+        }
+    }
+}
+
+/// Bark 推送参数
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BarkConfig {
+    /// Bark 服务端地址，自建 bark-server 时修改
+    pub bark_server: String,
+    /// 接收推送的设备，一次推送发往全部设备
+    #[serde(deserialize_with = "deserialize_bark_keys")]
+    pub bark_key: Vec<BarkDevice>,
+    /// AES 密钥，长度须为 16、24 或 32 字节；IV 每次推送随机生成
     pub aes_key: String,
-    pub aes_iv: String,
+    pub aes_mode: AesMode,
+    /// 该渠道默认的附加推送参数，可被路由规则覆盖
+    pub bark_options: BarkOptions,
+}
+
+impl Default for BarkConfig {
+    fn default() -> Self {
+        Self {
+            bark_server: default_bark_server(),
+            bark_key: Vec::new(),
+            aes_key: String::new(),
+            aes_mode: AesMode::default(),
+            bark_options: BarkOptions::default(),
+        }
+    }
+}
+
+/// Telegram 消息格式
+#[derive(Clone, Copy, Default, Serialize, Deserialize)]
+pub enum TelegramParseMode {
+    #[default]
+    #[serde(rename = "HTML")]
+    Html,
+    MarkdownV2,
+}
+
+/// Telegram Bot 推送参数
+#[derive(Clone, Serialize, Deserialize)]
+pub struct TelegramConfig {
+    pub bot_token: String,
+    /// 目标会话 ID（用户、群组或 @频道名）
+    pub chat_id: String,
+    /// Bot API 地址，可指向自建 Bot API 服务器
+    #[serde(default = "default_telegram_api_base")]
+    pub api_base: String,
+    #[serde(default)]
+    pub parse_mode: TelegramParseMode,
+}
+
+/// 通用 HTTP Webhook 推送参数
+#[derive(Clone, Serialize, Deserialize)]
+pub struct WebhookConfig {
+    pub url: String,
+    /// 额外请求头
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    /// JSON 请求体模板，字符串中的 {{sender}}、{{body}} 等占位符会被替换；为空时使用默认模板
+    #[serde(default)]
+    pub template: Option<serde_json::Value>,
+    /// HMAC-SHA256 签名密钥，为空时不签名
+    #[serde(default)]
+    pub secret: String,
+    /// 签名所在的请求头
+    #[serde(default = "default_signature_header")]
+    pub signature_header: String,
+}
+
+/// SMTP 连接的加密方式
+#[derive(Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpTls {
+    /// 明文连接后升级（默认端口 587）
+    #[default]
+    Starttls,
+    /// 直接 TLS 连接（默认端口 465）
+    Implicit,
+    /// 不加密（默认端口 25），仅用于本机或内网中继
+    None,
+}
+
+/// SMTP 邮件推送参数
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SmtpConfig {
+    pub host: String,
+    /// 端口，为空时按 tls 取默认值
+    #[serde(default)]
+    pub port: Option<u16>,
+    #[serde(default)]
+    pub tls: SmtpTls,
+    /// 登录用户名，为空时不认证
+    #[serde(default)]
+    pub username: String,
+    #[serde(default)]
+    pub password: String,
+    pub from: String,
+    pub to: Vec<String>,
+    /// 邮件主题模板，支持 {{sender}}、{{title}}
+    #[serde(default = "default_smtp_subject_template")]
+    pub subject_template: String,
+}
+
+/// 网页登录账号
+#[derive(Clone, Serialize, Deserialize)]
+pub struct AuthUser {
+    pub username: String,
+    /// Argon2 哈希（PHC 字符串），可用 `sms-forwarder hash-password <密码>` 生成
+    pub password_hash: String,
+}
+
+/// HTTP 认证参数
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AuthConfig {
+    pub users: Vec<AuthUser>,
+    /// API 客户端使用的 Bearer token
+    pub api_tokens: Vec<String>,
+    /// 登录会话有效秒数
+    pub session_ttl_secs: u64,
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        Self {
+            users: Vec::new(),
+            api_tokens: Vec::new(),
+            session_ttl_secs: default_session_ttl_secs(),
+        }
+    }
+}
+
+/// 一个通知渠道实例
+#[derive(Clone, Serialize, Deserialize)]
+pub struct NotifierConfig {
+    /// 渠道名称，用于日志与发件箱中区分各渠道
+    pub name: String,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    #[serde(flatten)]
+    pub kind: NotifierKind,
+}
+
+/// 渠道类型及其参数，JSON 中以 `type` 字段区分
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum NotifierKind {
+    Bark(BarkConfig),
+    Telegram(TelegramConfig),
+    Webhook(WebhookConfig),
+    Smtp(SmtpConfig),
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Config {
+    /// 顶层 bark_key/aes_key/aes_mode：默认的 Bark 渠道（名为 "bark"），bark_key 为空时不启用
+    #[serde(flatten)]
+    pub bark: BarkConfig,
+    /// 其他通知渠道，可配置任意多个
+    #[serde(default)]
+    pub notifiers: Vec<NotifierConfig>,
     /// 短信内容包含任一关键字时，以紧急通知推送
     pub emergency_keywords: Vec<String>,
+    /// 路由规则，按顺序匹配，可选择渠道、推送级别或丢弃短信
+    #[serde(default)]
+    pub rules: Vec<crate::rules::Rule>,
+    /// 时区（IANA 名称，如 Asia/Shanghai），用于免打扰时段与规则的时段匹配；为空时使用系统时区
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timezone: Option<String>,
+    /// 免打扰时段，紧急通知不受影响
+    #[serde(default)]
+    pub quiet_hours: Vec<crate::quiet::QuietHours>,
+    /// 识别验证码的正则列表，按顺序匹配；为空时不识别
+    #[serde(default = "crate::otp::default_patterns")]
+    pub otp_patterns: Vec<String>,
     /// 调制解调器串口设备路径（AT 指令直连）
     #[serde(default = "default_modem_device")]
     pub modem_device: String,
     /// 串口波特率
     #[serde(default = "default_baud_rate")]
     pub baud_rate: u32,
+    /// 兜底轮询（AT+CMGL）间隔秒数；新短信主要依靠 +CMTI 主动上报
+    #[serde(default = "default_poll_interval_secs")]
+    pub poll_interval_secs: u64,
+    /// 收短信模式：text 或 pdu
+    #[serde(default)]
+    pub sms_mode: SmsMode,
+    /// 长短信分段等待秒数，超时未收齐则按不完整消息转发（仅 PDU 模式可识别分段）
+    #[serde(default = "default_concat_timeout_secs")]
+    pub concat_timeout_secs: u64,
+    /// 通知在发件箱中的最长保留秒数，超过仍未送达则丢弃
+    #[serde(default = "default_outbox_max_age_secs")]
+    pub outbox_max_age_secs: u64,
+    /// 通知重试的最大退避间隔（秒）
+    #[serde(default = "default_outbox_max_backoff_secs")]
+    pub outbox_max_backoff_secs: u64,
+    /// 短信历史保留天数，0 表示永久保留
+    #[serde(default = "default_history_retention_days")]
+    pub history_retention_days: u32,
+    /// 发短信时请求状态报告，收到后更新发信记录的送达状态
+    #[serde(default = "default_true")]
+    pub delivery_reports: bool,
+    /// 收到最终状态报告（送达、失败或过期）时推送通知到所有已启用渠道
+    #[serde(default)]
+    pub delivery_report_notify: bool,
+    /// 每分钟最多发送的短信条数，0 表示不限制
+    #[serde(default = "default_send_rate_limit")]
+    pub send_rate_limit: u32,
+    /// 每分钟向同一号码最多发送的短信条数，0 表示不限制
+    #[serde(default)]
+    pub send_rate_limit_per_number: u32,
+    /// 网络拥塞等临时错误时的最多尝试次数（含第一次）
+    #[serde(default = "default_send_max_attempts")]
+    pub send_max_attempts: u32,
+    /// 短信在发送队列中的最长等待秒数，超过仍未发出则放弃
+    #[serde(default = "default_send_max_age_secs")]
+    pub send_max_age_secs: u64,
+    /// 网页与 API 的认证
+    #[serde(default)]
+    pub auth: AuthConfig,
+    /// HTTP 监听地址，IPv4/IPv6 地址或主机名（如 0.0.0.0、::、127.0.0.1）
+    #[serde(default = "default_listen_addr")]
+    pub listen_addr: String,
+    /// HTTP 监听端口
+    #[serde(default = "default_listen_port")]
+    pub listen_port: u16,
+    /// Unix 域套接字路径；设置后改为监听该套接字，不再监听 TCP 端口
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unix_socket: Option<String>,
+    /// TLS 证书链（PEM）路径，与 tls_key 同时设置时启用 HTTPS
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tls_cert: Option<String>,
+    /// TLS 私钥（PEM）路径
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tls_key: Option<String>,
 }
 
 impl Config {
+    /// 所有通知渠道（含顶层默认 Bark），按配置顺序
+    pub fn notifiers(&self) -> Vec<NotifierConfig> {
+        let mut list = Vec::with_capacity(self.notifiers.len() + 1);
+        if !self.bark.bark_key.is_empty() {
+            list.push(NotifierConfig {
+                name: "bark".to_string(),
+                enabled: true,
+                kind: NotifierKind::Bark(self.bark.clone()),
+            });
+        }
+        list.extend(self.notifiers.iter().cloned());
+        list
+    }
+
+    /// 检查无法在运行时自行纠正的配置错误（Bark 的 AES 密钥长度、正则、规则引用的渠道、时区、认证、监听设置），全部问题合并为一条错误
+    pub fn validate(&self) -> anyhow::Result<()> {
+        let mut problems = Vec::new();
+        for n in self.notifiers() {
+            let NotifierKind::Bark(bark) = &n.kind else {
+                continue;
+            };
+            if !n.enabled {
+                continue;
+            }
+            let mut check = |owner: String, key: &str| {
+                if ![16, 24, 32].contains(&key.len()) {
+                    problems.push(format!(
+                        "{}: aes_key 长度为 {} 字节，应为 16、24 或 32 字节",
+                        owner,
+                        key.len()
+                    ));
+                }
+            };
+            // 全部设备都有自己的 key 时，渠道级 aes_key 可以留空
+            if bark.bark_key.iter().any(|d| d.aes_key.is_none()) {
+                check(format!("渠道 {}", n.name), &bark.aes_key);
+            }
+            for (i, device) in bark.bark_key.iter().enumerate() {
+                if let Some(key) = &device.aes_key {
+                    check(format!("渠道 {} 设备 #{}", n.name, i + 1), key);
+                }
+            }
+        }
+        if let Err(e) = crate::otp::Extractor::new(&self.otp_patterns) {
+            problems.push(e.to_string());
+        }
+        if let Err(e) = crate::rules::Engine::new(&self.rules) {
+            problems.push(e.to_string());
+        }
+        if let Some(tz) = &self.timezone {
+            if tz.parse::<chrono_tz::Tz>().is_err() {
+                problems.push(format!("未知的时区 {}", tz));
+            }
+        }
+        let names: Vec<String> = self.notifiers().into_iter().map(|n| n.name).collect();
+        for (i, rule) in self.rules.iter().enumerate() {
+            for name in rule.action.notifiers.iter().flatten() {
+                if !names.contains(name) {
+                    problems.push(format!("规则 #{} 引用了不存在的渠道 {}", i + 1, name));
+                }
+            }
+        }
+        if self.auth.users.is_empty() && self.auth.api_tokens.iter().all(|t| t.is_empty()) {
+            problems.push("auth 中至少需要一个登录账号或 API token".to_string());
+        }
+        for user in &self.auth.users {
+            if argon2::PasswordHash::new(&user.password_hash).is_err() {
+                problems.push(format!("账号 {} 的 password_hash 不是有效的 Argon2 哈希", user.username));
+            }
+        }
+        if self.tls_cert.is_some() != self.tls_key.is_some() {
+            problems.push("tls_cert 与 tls_key 需同时设置".to_string());
+        }
+        if self.unix_socket.is_some() && self.tls_cert.is_some() {
+            problems.push("unix_socket 不支持 TLS，请去掉 tls_cert/tls_key".to_string());
+        }
+        if !problems.is_empty() {
+            anyhow::bail!(problems.join("; "));
+        }
+        Ok(())
+    }
+
+    /// 配置的时区；无法识别时退回系统时区
+    pub fn zone(&self) -> crate::quiet::Zone {
+        match self.timezone.as_deref().map(str::parse) {
+            Some(Ok(tz)) => crate::quiet::Zone::Named(tz),
+            _ => crate::quiet::Zone::Local,
+        }
+    }
+
     pub fn load() -> Self {
         tracing::debug!("reading config.json");
         let txt = fs::read_to_string("config.json").unwrap();
@@ -40,3 +509,31 @@ impl Config {
         ).unwrap();
     }
 }
+
+/// 运行时共享的配置：POST /config 保存后替换，各后台任务每次使用时读取最新值，也可订阅变更
+#[derive(Clone)]
+pub struct SharedConfig {
+    tx: Arc<watch::Sender<Arc<Config>>>,
+}
+
+impl SharedConfig {
+    pub fn new(cfg: Config) -> Self {
+        Self {
+            tx: Arc::new(watch::Sender::new(Arc::new(cfg))),
+        }
+    }
+
+    /// 当前配置的快照
+    pub fn get(&self) -> Arc<Config> {
+        self.tx.borrow().clone()
+    }
+
+    /// 替换配置并通知所有订阅者
+    pub fn set(&self, cfg: Config) {
+        self.tx.send_replace(Arc::new(cfg));
+    }
+
+    pub fn subscribe(&self) -> watch::Receiver<Arc<Config>> {
+        self.tx.subscribe()
+    }
+}
@@ -0,0 +1,847 @@
+// 短信历史：收到的每条短信（含原始 PDU/文本与各渠道转发状态）和每次发送短信（含各段的送达状态）都记入本地 SQLite
+// 从 SIM 删除后仍可查询；超过保留天数的记录由后台任务定期清理
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::{DateTime, FixedOffset, Utc};
+use rusqlite::types::Value;
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+
+use crate::config::SharedConfig;
+
+const HISTORY_FILE: &str = "history.db";
+/// 清理过期记录的间隔
+const PRUNE_INTERVAL: Duration = Duration::from_secs(3600);
+/// 状态报告只匹配这段时间内发出的短信（消息参考号只有 256 个，会循环使用）
+const REPORT_MATCH_DAYS: i64 = 7;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS inbound (
+    id            INTEGER PRIMARY KEY AUTOINCREMENT,
+    received_at   TEXT NOT NULL,
+    sender        TEXT NOT NULL,
+    sms_timestamp TEXT,
+    body          TEXT NOT NULL,
+    -- 各分段的原始内容（PDU 十六进制或文本模式正文），JSON 数组
+    raw           TEXT NOT NULL,
+    complete      INTEGER NOT NULL,
+    -- 被路由规则丢弃时为命中的规则名
+    dropped_by    TEXT
+);
+CREATE INDEX IF NOT EXISTS inbound_received_at ON inbound (received_at);
+CREATE INDEX IF NOT EXISTS inbound_sender ON inbound (sender);
+
+CREATE TABLE IF NOT EXISTS forwards (
+    inbound_id INTEGER NOT NULL REFERENCES inbound (id) ON DELETE CASCADE,
+    notifier   TEXT NOT NULL,
+    status     TEXT NOT NULL,
+    attempts   INTEGER NOT NULL DEFAULT 0,
+    last_error TEXT,
+    updated_at TEXT NOT NULL,
+    PRIMARY KEY (inbound_id, notifier)
+);
+
+CREATE TABLE IF NOT EXISTS outbound (
+    id          INTEGER PRIMARY KEY AUTOINCREMENT,
+    created_at  TEXT NOT NULL,
+    number      TEXT NOT NULL,
+    text        TEXT NOT NULL,
+    status      TEXT NOT NULL,
+    message_ref INTEGER,
+    error       TEXT
+);
+CREATE INDEX IF NOT EXISTS outbound_created_at ON outbound (created_at);
+
+CREATE TABLE IF NOT EXISTS outbound_parts (
+    outbound_id   INTEGER NOT NULL REFERENCES outbound (id) ON DELETE CASCADE,
+    seq           INTEGER NOT NULL,
+    message_ref   INTEGER,
+    status        TEXT NOT NULL,
+    -- 状态报告中的 TP-ST
+    report_status INTEGER,
+    updated_at    TEXT NOT NULL,
+    PRIMARY KEY (outbound_id, seq)
+);
+CREATE INDEX IF NOT EXISTS outbound_parts_message_ref ON outbound_parts (message_ref);
+";
+
+/// 单个渠道的转发状态
+#[derive(Clone, Copy)]
+pub enum ForwardStatus {
+    /// 已写入发件箱，等待发送或重试
+    Pending,
+    Delivered,
+    /// 超过发件箱最长保留时间仍未送达
+    Expired,
+    /// 渠道已被删除或停用，不再发送
+    Dropped,
+}
+
+impl ForwardStatus {
+    fn as_str(self) -> &'static str {
+        match self {
+            ForwardStatus::Pending => "pending",
+            ForwardStatus::Delivered => "delivered",
+            ForwardStatus::Expired => "expired",
+            ForwardStatus::Dropped => "dropped",
+        }
+    }
+}
+
+/// 发出短信的送达状态；整条短信的状态由各段汇总
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DeliveryStatus {
+    /// 已提交给短信中心，尚未收到最终状态报告
+    Sent,
+    Delivered,
+    Failed,
+    /// 有效期内未能送达
+    Expired,
+}
+
+impl DeliveryStatus {
+    /// 按状态报告的 TP-ST 分类；短信中心仍在重试的临时错误视为 Sent
+    pub fn from_tp_status(st: u8) -> Self {
+        match st {
+            0x00..=0x1F => DeliveryStatus::Delivered,
+            0x20..=0x3F => DeliveryStatus::Sent,
+            0x46 => DeliveryStatus::Expired,
+            _ => DeliveryStatus::Failed,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DeliveryStatus::Sent => "sent",
+            DeliveryStatus::Delivered => "delivered",
+            DeliveryStatus::Failed => "failed",
+            DeliveryStatus::Expired => "expired",
+        }
+    }
+
+    fn parse(s: &str) -> Self {
+        match s {
+            "delivered" => DeliveryStatus::Delivered,
+            "failed" => DeliveryStatus::Failed,
+            "expired" => DeliveryStatus::Expired,
+            _ => DeliveryStatus::Sent,
+        }
+    }
+
+    /// 汇总各段状态：任一段失败或过期即为失败或过期，全部送达才算送达
+    fn overall(parts: &[DeliveryStatus]) -> Self {
+        if parts.contains(&DeliveryStatus::Failed) {
+            DeliveryStatus::Failed
+        } else if parts.contains(&DeliveryStatus::Expired) {
+            DeliveryStatus::Expired
+        } else if !parts.is_empty() && parts.iter().all(|p| *p == DeliveryStatus::Delivered) {
+            DeliveryStatus::Delivered
+        } else {
+            DeliveryStatus::Sent
+        }
+    }
+}
+
+/// 状态报告对应到的发信记录
+pub struct ReportUpdate {
+    pub outbound_id: i64,
+    pub number: String,
+    pub text: String,
+    /// 报告对应的分段序号（从 1 开始）与总段数
+    pub seq: u32,
+    pub segments: u32,
+    /// 整条短信更新后的状态
+    pub status: DeliveryStatus,
+    /// 整条短信的状态是否因本次报告而改变
+    pub changed: bool,
+}
+
+/// 一条收到的短信
+pub struct Inbound<'a> {
+    pub sender: &'a str,
+    pub timestamp: Option<DateTime<FixedOffset>>,
+    pub body: &'a str,
+    pub raw: &'a [String],
+    pub complete: bool,
+}
+
+/// 短信方向
+#[derive(Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    Inbound,
+    Outbound,
+}
+
+/// 历史查询条件，未设置的条件不过滤
+#[derive(Default)]
+pub struct Filter {
+    pub direction: Option<Direction>,
+    /// 对方号码（收信为发送方，发信为收件人），精确匹配
+    pub number: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    /// 正文或号码中包含的文字
+    pub text: Option<String>,
+}
+
+/// 某条收到的短信在一个渠道的转发状态
+#[derive(Serialize)]
+pub struct Forward {
+    pub notifier: String,
+    pub status: String,
+    pub attempts: u32,
+    pub last_error: Option<String>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// 发出短信中一段的送达状态
+#[derive(Serialize)]
+pub struct Part {
+    pub seq: u32,
+    pub message_ref: Option<u8>,
+    pub status: String,
+    /// 状态报告中的 TP-ST
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub report_status: Option<u8>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// 查询结果中的一条短信
+#[derive(Serialize)]
+pub struct Message {
+    pub id: i64,
+    pub direction: Direction,
+    pub number: String,
+    pub body: String,
+    /// 收到或发送的时间
+    pub at: DateTime<Utc>,
+    /// 收信：服务中心时间戳
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sms_timestamp: Option<DateTime<FixedOffset>>,
+    /// 收信：原始 PDU / 文本
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub raw: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub complete: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dropped_by: Option<String>,
+    /// 收信：各渠道的转发状态
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub forwards: Vec<Forward>,
+    /// 发信：queued / sent / delivered / failed / expired
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<String>,
+    /// 发信：第一段的消息参考号
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message_ref: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// 发信：各段的送达状态
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub parts: Vec<Part>,
+}
+
+/// 一页查询结果
+#[derive(Serialize)]
+pub struct Page<T> {
+    pub total: u64,
+    pub page: u32,
+    pub per_page: u32,
+    pub items: Vec<T>,
+}
+
+/// 按号码归组的会话概要
+#[derive(Serialize)]
+pub struct Conversation {
+    pub number: String,
+    pub count: u64,
+    pub last_at: DateTime<Utc>,
+    pub last_body: String,
+    pub last_direction: Direction,
+}
+
+/// 收信与发信合并后的视图，列名统一
+const MESSAGES_VIEW: &str = "
+    SELECT 'inbound' AS direction, id, sender AS number, body, received_at AS at,
+           sms_timestamp, raw, complete, dropped_by,
+           NULL AS status, NULL AS message_ref, NULL AS error
+      FROM inbound
+    UNION ALL
+    SELECT 'outbound', id, number, text, created_at,
+           NULL, NULL, NULL, NULL,
+           status, message_ref, error
+      FROM outbound
+";
+
+/// LIKE 模式中转义 % 与 _
+fn like_pattern(s: &str) -> String {
+    let escaped = s
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+    format!("%{}%", escaped)
+}
+
+fn direction(s: &str) -> Direction {
+    if s == "outbound" {
+        Direction::Outbound
+    } else {
+        Direction::Inbound
+    }
+}
+
+impl Filter {
+    /// 生成 WHERE 子句与对应参数
+    fn to_sql(&self) -> (String, Vec<Value>) {
+        let mut clauses = Vec::new();
+        let mut args: Vec<Value> = Vec::new();
+        if let Some(d) = self.direction {
+            clauses.push("direction = ?");
+            args.push(Value::Text(
+                match d {
+                    Direction::Inbound => "inbound",
+                    Direction::Outbound => "outbound",
+                }
+                .to_string(),
+            ));
+        }
+        if let Some(n) = &self.number {
+            clauses.push("number = ?");
+            args.push(Value::Text(n.clone()));
+        }
+        // 时间列与参数使用同一格式，可直接按字符串比较
+        if let Some(t) = self.since {
+            clauses.push("at >= ?");
+            args.push(Value::Text(t.format("%F %T%.f%:z").to_string()));
+        }
+        if let Some(t) = self.until {
+            clauses.push("at < ?");
+            args.push(Value::Text(t.format("%F %T%.f%:z").to_string()));
+        }
+        if let Some(q) = self.text.as_deref().filter(|q| !q.is_empty()) {
+            clauses.push("(body LIKE ? ESCAPE '\\' OR number LIKE ? ESCAPE '\\')");
+            args.push(Value::Text(like_pattern(q)));
+            args.push(Value::Text(like_pattern(q)));
+        }
+        if clauses.is_empty() {
+            (String::new(), args)
+        } else {
+            (format!("WHERE {}", clauses.join(" AND ")), args)
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct History {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl History {
+    /// 打开（必要时创建）工作目录下的 history.db
+    pub fn open() -> anyhow::Result<Self> {
+        Self::open_at(HISTORY_FILE)
+    }
+
+    /// 测试用的内存数据库
+    #[cfg(test)]
+    pub fn in_memory() -> Self {
+        Self::open_at(":memory:").unwrap()
+    }
+
+    fn open_at(path: &str) -> anyhow::Result<Self> {
+        let conn = Connection::open(path)?;
+        conn.execute_batch("PRAGMA journal_mode = WAL; PRAGMA foreign_keys = ON;")?;
+        conn.execute_batch(SCHEMA)?;
+        Ok(Self {
+            conn: Arc::new(Mutex::new(conn)),
+        })
+    }
+
+    /// 记录收到的短信，返回记录 ID。转发失败的短信留在 SIM 上会被再次读到，
+    /// 号码、时间戳、正文与原始内容都相同时视为同一条，返回已有记录而不重复插入
+    pub fn record_inbound(&self, msg: &Inbound) -> anyhow::Result<i64> {
+        let conn = self.conn.lock().unwrap();
+        let raw = serde_json::to_string(msg.raw)?;
+        if msg.timestamp.is_some() {
+            let existing = conn
+                .query_row(
+                    "SELECT id FROM inbound
+                      WHERE sender = ?1 AND sms_timestamp = ?2 AND body = ?3 AND raw = ?4
+                      ORDER BY id DESC LIMIT 1",
+                    params![msg.sender, msg.timestamp, msg.body, raw],
+                    |r| r.get(0),
+                )
+                .optional()?;
+            if let Some(id) = existing {
+                return Ok(id);
+            }
+        }
+        conn.execute(
+            "INSERT INTO inbound (received_at, sender, sms_timestamp, body, raw, complete)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                Utc::now(),
+                msg.sender,
+                msg.timestamp,
+                msg.body,
+                raw,
+                msg.complete,
+            ],
+        )?;
+        Ok(conn.last_insert_rowid())
+    }
+
+    /// 标记短信被路由规则丢弃
+    pub fn mark_dropped(&self, id: i64, rule: &str) -> anyhow::Result<()> {
+        self.conn.lock().unwrap().execute(
+            "UPDATE inbound SET dropped_by = ?2 WHERE id = ?1",
+            params![id, rule],
+        )?;
+        Ok(())
+    }
+
+    /// 更新某条短信在某个渠道的转发状态；last_error 为最近一次失败原因
+    pub fn record_forward(
+        &self,
+        id: i64,
+        notifier: &str,
+        status: ForwardStatus,
+        attempts: u32,
+        last_error: Option<&str>,
+    ) -> anyhow::Result<()> {
+        self.conn.lock().unwrap().execute(
+            "INSERT INTO forwards (inbound_id, notifier, status, attempts, last_error, updated_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+             ON CONFLICT (inbound_id, notifier) DO UPDATE SET
+                 status = excluded.status,
+                 attempts = excluded.attempts,
+                 last_error = COALESCE(excluded.last_error, forwards.last_error),
+                 updated_at = excluded.updated_at",
+            params![id, notifier, status.as_str(), attempts, last_error, Utc::now()],
+        )?;
+        Ok(())
+    }
+
+    /// 记录一条进入发送队列的短信，状态为 queued
+    pub fn queue_outbound(&self, number: &str, text: &str) -> anyhow::Result<i64> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO outbound (created_at, number, text, status) VALUES (?1, ?2, ?3, 'queued')",
+            params![Utc::now(), number, text],
+        )?;
+        Ok(conn.last_insert_rowid())
+    }
+
+    /// 发送失败、等待重试：记下最近一次错误，状态仍为 queued
+    pub fn outbound_retrying(&self, id: i64, error: &str) -> anyhow::Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute("UPDATE outbound SET error = ?2 WHERE id = ?1", params![id, error])?;
+        Ok(())
+    }
+
+    /// 记录发送结果；成功时参数为各段 +CMGS 返回的消息参考号，同时记录各段，等待状态报告
+    pub fn complete_outbound(&self, id: i64, result: Result<&[Option<u8>], &str>) -> anyhow::Result<()> {
+        let (status, refs, error) = match result {
+            Ok(refs) => ("sent", refs, None),
+            Err(e) => ("failed", &[][..], Some(e)),
+        };
+        let now = Utc::now();
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        tx.execute(
+            "UPDATE outbound SET status = ?2, message_ref = ?3, error = ?4 WHERE id = ?1",
+            params![id, status, refs.first().copied().flatten(), error],
+        )?;
+        for (i, mr) in refs.iter().enumerate() {
+            tx.execute(
+                "INSERT INTO outbound_parts (outbound_id, seq, message_ref, status, updated_at)
+                 VALUES (?1, ?2, ?3, 'sent', ?4)",
+                params![id, i as u32 + 1, mr, now],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// 按状态报告更新对应分段及整条短信的状态；找不到对应的已发短信时返回 None
+    pub fn record_report(
+        &self,
+        recipient: &str,
+        message_ref: u8,
+        tp_status: u8,
+    ) -> anyhow::Result<Option<ReportUpdate>> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let since = Utc::now() - chrono::Duration::days(REPORT_MATCH_DAYS);
+        let found = {
+            let mut stmt = tx.prepare(
+                "SELECT p.outbound_id, p.seq, o.number, o.text, o.status
+                   FROM outbound_parts p JOIN outbound o ON o.id = p.outbound_id
+                  WHERE p.message_ref = ?1 AND p.status = 'sent' AND o.created_at >= ?2
+                  ORDER BY o.created_at DESC, p.seq",
+            )?;
+            let rows = stmt
+                .query_map(params![message_ref, since], |r| {
+                    Ok((
+                        r.get::<_, i64>(0)?,
+                        r.get::<_, u32>(1)?,
+                        r.get::<_, String>(2)?,
+                        r.get::<_, String>(3)?,
+                        r.get::<_, String>(4)?,
+                    ))
+                })?
+                .collect::<Result<Vec<_>, _>>()?;
+            rows.into_iter().find(|row| same_number(&row.2, recipient))
+        };
+        let Some((outbound_id, seq, number, text, previous)) = found else {
+            return Ok(None);
+        };
+        tx.execute(
+            "UPDATE outbound_parts SET status = ?3, report_status = ?4, updated_at = ?5
+              WHERE outbound_id = ?1 AND seq = ?2",
+            params![
+                outbound_id,
+                seq,
+                DeliveryStatus::from_tp_status(tp_status).as_str(),
+                tp_status,
+                Utc::now()
+            ],
+        )?;
+        let parts = {
+            let mut stmt = tx.prepare("SELECT status FROM outbound_parts WHERE outbound_id = ?1")?;
+            let rows = stmt
+                .query_map(params![outbound_id], |r| r.get::<_, String>(0))?
+                .collect::<Result<Vec<_>, _>>()?;
+            rows.iter().map(|s| DeliveryStatus::parse(s)).collect::<Vec<_>>()
+        };
+        let status = DeliveryStatus::overall(&parts);
+        let changed = status != DeliveryStatus::parse(&previous);
+        if changed {
+            tx.execute(
+                "UPDATE outbound SET status = ?2 WHERE id = ?1",
+                params![outbound_id, status.as_str()],
+            )?;
+        }
+        tx.commit()?;
+        Ok(Some(ReportUpdate {
+            outbound_id,
+            number,
+            text,
+            seq,
+            segments: parts.len() as u32,
+            status,
+            changed,
+        }))
+    }
+
+    /// 一条发信记录及其各段状态
+    pub fn outbound(&self, id: i64) -> anyhow::Result<Option<Message>> {
+        let conn = self.conn.lock().unwrap();
+        let sql = format!(
+            "SELECT * FROM ({}) WHERE direction = 'outbound' AND id = ?1",
+            MESSAGES_VIEW
+        );
+        let message = conn
+            .query_row(&sql, params![id], message_from_row)
+            .optional()?;
+        let Some(mut message) = message else {
+            return Ok(None);
+        };
+        message.parts = parts_of(&conn, id)?;
+        Ok(Some(message))
+    }
+
+    /// 按条件分页查询收发记录，新的在前；page 从 1 开始
+    pub fn messages(&self, filter: &Filter, page: u32, per_page: u32) -> anyhow::Result<Page<Message>> {
+        let (cond, args) = filter.to_sql();
+        let conn = self.conn.lock().unwrap();
+        let total: u64 = conn.query_row(
+            &format!("SELECT COUNT(*) FROM ({}) {}", MESSAGES_VIEW, cond),
+            params_from_iter(args.iter()),
+            |r| r.get(0),
+        )?;
+        let sql = format!(
+            "SELECT * FROM ({}) {} ORDER BY at DESC, id DESC LIMIT {} OFFSET {}",
+            MESSAGES_VIEW,
+            cond,
+            per_page,
+            (page.max(1) - 1) as u64 * per_page as u64
+        );
+        let mut stmt = conn.prepare(&sql)?;
+        let mut items = stmt
+            .query_map(params_from_iter(args.iter()), message_from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut forwards = conn.prepare(
+            "SELECT notifier, status, attempts, last_error, updated_at
+               FROM forwards WHERE inbound_id = ?1 ORDER BY notifier",
+        )?;
+        for m in items.iter_mut().filter(|m| matches!(m.direction, Direction::Inbound)) {
+            m.forwards = forwards
+                .query_map(params![m.id], |r| {
+                    Ok(Forward {
+                        notifier: r.get(0)?,
+                        status: r.get(1)?,
+                        attempts: r.get(2)?,
+                        last_error: r.get(3)?,
+                        updated_at: r.get(4)?,
+                    })
+                })?
+                .collect::<Result<Vec<_>, _>>()?;
+        }
+        for m in items.iter_mut().filter(|m| matches!(m.direction, Direction::Outbound)) {
+            m.parts = parts_of(&conn, m.id)?;
+        }
+        Ok(Page {
+            total,
+            page: page.max(1),
+            per_page,
+            items,
+        })
+    }
+
+    /// 按号码归组的会话列表，最近有往来的在前；filter 同 messages
+    pub fn conversations(
+        &self,
+        filter: &Filter,
+        page: u32,
+        per_page: u32,
+    ) -> anyhow::Result<Page<Conversation>> {
+        let (cond, args) = filter.to_sql();
+        let conn = self.conn.lock().unwrap();
+        let total: u64 = conn.query_row(
+            &format!(
+                "SELECT COUNT(DISTINCT number) FROM ({}) {}",
+                MESSAGES_VIEW, cond
+            ),
+            params_from_iter(args.iter()),
+            |r| r.get(0),
+        )?;
+        // SQLite 的 max() 聚合会让同一行的其他列取自最大值所在行
+        let sql = format!(
+            "SELECT number, COUNT(*), max(at), body, direction FROM ({}) {}
+              GROUP BY number ORDER BY max(at) DESC LIMIT {} OFFSET {}",
+            MESSAGES_VIEW,
+            cond,
+            per_page,
+            (page.max(1) - 1) as u64 * per_page as u64
+        );
+        let mut stmt = conn.prepare(&sql)?;
+        let items = stmt
+            .query_map(params_from_iter(args.iter()), |r| {
+                Ok(Conversation {
+                    number: r.get(0)?,
+                    count: r.get(1)?,
+                    last_at: r.get(2)?,
+                    last_body: r.get(3)?,
+                    last_direction: direction(&r.get::<_, String>(4)?),
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Page {
+            total,
+            page: page.max(1),
+            per_page,
+            items,
+        })
+    }
+
+    /// 删除早于保留期限的记录，返回删除条数
+    pub fn prune(&self, retention_days: u32) -> anyhow::Result<usize> {
+        let cutoff = Utc::now() - chrono::Duration::days(retention_days as i64);
+        let conn = self.conn.lock().unwrap();
+        let inbound = conn.execute("DELETE FROM inbound WHERE received_at < ?1", params![cutoff])?;
+        let outbound = conn.execute("DELETE FROM outbound WHERE created_at < ?1", params![cutoff])?;
+        Ok(inbound + outbound)
+    }
+
+    /// 后台清理任务，每次按最新配置的保留天数清理；为 0 时永久保留
+    pub async fn run_retention(self, cfg: SharedConfig) {
+        loop {
+            let retention_days = cfg.get().history_retention_days;
+            if retention_days > 0 {
+                match self.prune(retention_days) {
+                    Ok(0) => {}
+                    Ok(n) => tracing::info!(deleted = n, "pruned SMS history"),
+                    Err(e) => tracing::error!(err = %e, "prune SMS history failed"),
+                }
+            }
+            tokio::time::sleep(PRUNE_INTERVAL).await;
+        }
+    }
+}
+
+/// 状态报告中的号码可能缺少国家码或 +，按数字后缀比较；任一方没有数字时视为不同，
+/// 避免号码解析失败的报告按消息参考号误配到其他短信
+fn same_number(a: &str, b: &str) -> bool {
+    let digits = |s: &str| s.chars().filter(char::is_ascii_digit).collect::<String>();
+    let (a, b) = (digits(a), digits(b));
+    if a.is_empty() || b.is_empty() {
+        return false;
+    }
+    let (short, long) = if a.len() <= b.len() { (a, b) } else { (b, a) };
+    long.ends_with(&short) && (short.len() >= 7 || short.len() == long.len())
+}
+
+/// 发信记录的各段状态
+fn parts_of(conn: &Connection, outbound_id: i64) -> rusqlite::Result<Vec<Part>> {
+    let mut stmt = conn.prepare_cached(
+        "SELECT seq, message_ref, status, report_status, updated_at
+           FROM outbound_parts WHERE outbound_id = ?1 ORDER BY seq",
+    )?;
+    let rows = stmt.query_map(params![outbound_id], |r| {
+        Ok(Part {
+            seq: r.get(0)?,
+            message_ref: r.get(1)?,
+            status: r.get(2)?,
+            report_status: r.get(3)?,
+            updated_at: r.get(4)?,
+        })
+    })?;
+    rows.collect()
+}
+
+fn message_from_row(r: &Row) -> rusqlite::Result<Message> {
+    let raw: Option<String> = r.get("raw")?;
+    Ok(Message {
+        direction: direction(&r.get::<_, String>("direction")?),
+        id: r.get("id")?,
+        number: r.get("number")?,
+        body: r.get("body")?,
+        at: r.get("at")?,
+        sms_timestamp: r.get("sms_timestamp")?,
+        raw: raw.and_then(|s| serde_json::from_str(&s).ok()),
+        complete: r.get("complete")?,
+        dropped_by: r.get("dropped_by")?,
+        forwards: Vec::new(),
+        status: r.get("status")?,
+        message_ref: r.get("message_ref")?,
+        error: r.get("error")?,
+        parts: Vec::new(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn history() -> History {
+        History::in_memory()
+    }
+
+    #[test]
+    fn same_sms_is_recorded_once() {
+        let h = history();
+        let ts = DateTime::parse_from_rfc3339("2024-05-06T12:34:56+08:00").unwrap();
+        let raw = vec!["0004...".to_string()];
+        let msg = Inbound {
+            sender: "+8613800138000",
+            timestamp: Some(ts),
+            body: "hello",
+            raw: &raw,
+            complete: true,
+        };
+        let id = h.record_inbound(&msg).unwrap();
+        assert_eq!(h.record_inbound(&msg).unwrap(), id);
+        let other = Inbound {
+            body: "hello again",
+            ..msg
+        };
+        assert_ne!(h.record_inbound(&other).unwrap(), id);
+        // 没有时间戳时无法判断是否为同一条
+        let untimed = Inbound {
+            timestamp: None,
+            ..msg
+        };
+        let first = h.record_inbound(&untimed).unwrap();
+        assert_ne!(h.record_inbound(&untimed).unwrap(), first);
+    }
+
+    /// 发出一条分两段的短信，各段消息参考号为 41、42
+    fn sent_two_parts(h: &History, number: &str) -> i64 {
+        let id = h.queue_outbound(number, "hello").unwrap();
+        h.complete_outbound(id, Ok(&[Some(41), Some(42)])).unwrap();
+        id
+    }
+
+    fn status_of(h: &History, id: i64) -> String {
+        h.outbound(id).unwrap().unwrap().status.unwrap()
+    }
+
+    #[test]
+    fn reports_update_parts_and_overall_status() {
+        let h = history();
+        let id = sent_two_parts(&h, "+8613800138000");
+        assert_eq!(status_of(&h, id), "sent");
+
+        let first = h.record_report("+8613800138000", 41, 0x00).unwrap().unwrap();
+        assert_eq!((first.outbound_id, first.seq, first.segments), (id, 1, 2));
+        assert_eq!(first.status, DeliveryStatus::Sent);
+        assert!(!first.changed);
+
+        // 状态报告中的号码缺少国家码
+        let second = h.record_report("13800138000", 42, 0x00).unwrap().unwrap();
+        assert_eq!(second.seq, 2);
+        assert_eq!(second.status, DeliveryStatus::Delivered);
+        assert!(second.changed);
+        assert_eq!(status_of(&h, id), "delivered");
+
+        let parts = h.outbound(id).unwrap().unwrap().parts;
+        assert_eq!(parts.len(), 2);
+        assert_eq!(parts[1].report_status, Some(0));
+        // 已有最终状态的分段不再匹配
+        assert!(h.record_report("+8613800138000", 41, 0x00).unwrap().is_none());
+    }
+
+    #[test]
+    fn one_expired_part_expires_the_message() {
+        let h = history();
+        let id = sent_two_parts(&h, "+8613800138000");
+        // 短信中心仍在重试，分段保持 sent
+        let retrying = h.record_report("+8613800138000", 41, 0x30).unwrap().unwrap();
+        assert_eq!(retrying.status, DeliveryStatus::Sent);
+        let expired = h.record_report("+8613800138000", 41, 0x46).unwrap().unwrap();
+        assert_eq!(expired.status, DeliveryStatus::Expired);
+        assert!(expired.changed);
+        h.record_report("+8613800138000", 42, 0x00).unwrap().unwrap();
+        assert_eq!(status_of(&h, id), "expired");
+    }
+
+    #[test]
+    fn report_must_match_recipient() {
+        let h = history();
+        let a = sent_two_parts(&h, "+8613800138000");
+        let b = sent_two_parts(&h, "+8613900139000");
+        assert!(h.record_report("+8613700137000", 41, 0x00).unwrap().is_none());
+        // 号码为空或未能解析的报告不能按参考号随便配上
+        assert!(h.record_report("", 41, 0x00).unwrap().is_none());
+        assert!(h.record_report("Operator", 41, 0x00).unwrap().is_none());
+        assert_eq!(h.record_report("+8613800138000", 41, 0x00).unwrap().unwrap().outbound_id, a);
+        assert_eq!(h.record_report("+8613900139000", 41, 0x00).unwrap().unwrap().outbound_id, b);
+    }
+
+    #[test]
+    fn failed_send_has_no_parts() {
+        let h = history();
+        let id = h.queue_outbound("+8613800138000", "hello").unwrap();
+        h.complete_outbound(id, Err("CMS error 42")).unwrap();
+        let msg = h.outbound(id).unwrap().unwrap();
+        assert_eq!(msg.status.as_deref(), Some("failed"));
+        assert_eq!(msg.error.as_deref(), Some("CMS error 42"));
+        assert!(msg.parts.is_empty());
+        assert!(h.record_report("+8613800138000", 41, 0x00).unwrap().is_none());
+    }
+
+    #[test]
+    fn number_matching() {
+        assert!(same_number("+8613800138000", "13800138000"));
+        assert!(same_number("10086", "10086"));
+        assert!(!same_number("10086", "110086"));
+        assert!(!same_number("", "+8613800138000"));
+        assert!(!same_number("+8613800138000", ""));
+        assert!(!same_number("", ""));
+    }
+}
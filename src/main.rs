@@ -1,11 +1,49 @@
+mod auth;
 mod config;
 mod bark;
+mod cms;
+mod concat;
+mod history;
 mod modem;
+mod notify;
+mod otp;
+mod outbox;
+mod pdu;
+mod quiet;
+mod rules;
+mod sendqueue;
+mod server;
+mod smtp;
+mod telegram;
 mod web;
+mod webhook;
 
-use config::Config;
+use config::{Config, SharedConfig};
+use history::History;
+use outbox::Outbox;
+use sendqueue::SendQueue;
 use tracing_subscriber::EnvFilter;
 
+/// 首次启动时生成的 admin 初始密码写入这个文件
+const INITIAL_PASSWORD_FILE: &str = "initial-admin-password.txt";
+
+/// 把初始密码写入仅属主可读写（0600）的新文件；先删除旧文件，避免沿用其权限
+fn write_initial_password(password: &str) -> anyhow::Result<()> {
+    use std::io::Write;
+    use std::os::unix::fs::OpenOptionsExt;
+    match std::fs::remove_file(INITIAL_PASSWORD_FILE) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+        _ => {}
+    }
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(INITIAL_PASSWORD_FILE)?;
+    writeln!(file, "{}", password)?;
+    Ok(())
+}
+
 #[tokio::main]
 async fn main() {
     tracing_subscriber::fmt()
@@ -14,16 +52,70 @@ async fn main() {
         )
         .init();
 
+    // sms-forwarder hash-password <密码>：输出可写入 auth.users 的密码哈希
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(String::as_str) == Some("hash-password") {
+        match args.get(2).map(|p| auth::hash_password(p)) {
+            Some(Ok(hash)) => println!("{}", hash),
+            Some(Err(e)) => eprintln!("{}", e),
+            None => eprintln!("usage: sms-forwarder hash-password <password>"),
+        }
+        return;
+    }
+
     tracing::info!("loading config");
-    let cfg = Config::load();
+    let mut cfg = Config::load();
+    // 首次启动未配置任何凭据时创建 admin 账号；初始密码写入仅属主可读的文件，不进入日志
+    if cfg.auth.users.is_empty() && cfg.auth.api_tokens.is_empty() {
+        let password = auth::random_secret(16);
+        let created = auth::hash_password(&password).and_then(|hash| {
+            write_initial_password(&password)?;
+            Ok(hash)
+        });
+        match created {
+            Ok(hash) => {
+                cfg.auth.users.push(config::AuthUser {
+                    username: "admin".to_string(),
+                    password_hash: hash,
+                });
+                cfg.save();
+                tracing::warn!(
+                    "未配置登录账号，已创建 admin 账号，初始密码已写入 {}（请妥善保存后删除该文件，可用 hash-password 生成新哈希替换）",
+                    INITIAL_PASSWORD_FILE
+                );
+            }
+            Err(e) => tracing::error!(err = %e, "create initial admin account failed"),
+        }
+    }
+    if let Err(e) = cfg.validate() {
+        tracing::error!(err = %e, "配置有误，请修改 config.json 后重启");
+        std::process::exit(1);
+    }
     tracing::info!("config loaded, emergency_keywords: {} items", cfg.emergency_keywords.len());
 
-    let app = web::router(cfg.clone());
+    let history = match History::open() {
+        Ok(h) => h,
+        Err(e) => {
+            tracing::error!(err = %e, "open history.db failed");
+            std::process::exit(1);
+        }
+    };
+    // 各任务共享同一份配置，POST /config 保存后立即生效（监听设置除外）
+    let shared = SharedConfig::new(cfg.clone());
+    tokio::spawn(history.clone().run_retention(shared.clone()));
+
+    let outbox = Outbox::load(history.clone());
+    tokio::spawn(outbox.clone().run(shared.clone()));
+
+    // 串口只由串口任务持有，收信与发信都通过它的句柄排队执行
+    let queue = SendQueue::load(history.clone());
+    let app = web::router(shared.clone(), outbox.clone(), history.clone(), queue.clone());
 
-    tokio::spawn(modem::start(cfg));
+    modem::start(shared, outbox, history, queue);
     tracing::info!("SMS poll task spawned");
 
-    let listener = tokio::net::TcpListener::bind("0.0.0.0:10086").await.unwrap();
-    tracing::info!("listening on 0.0.0.0:10086");
-    axum::serve(listener, app).await.unwrap();
+    if let Err(e) = server::serve(&cfg, app).await {
+        tracing::error!(err = %e, "HTTP 服务启动失败");
+        std::process::exit(1);
+    }
 }
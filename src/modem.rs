@@ -1,13 +1,33 @@
+use chrono::{DateTime, FixedOffset, NaiveDateTime};
+use std::collections::VecDeque;
+use std::sync::Arc;
 use std::time::Duration;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::time::{sleep, timeout};
+use tokio::sync::{mpsc, oneshot};
+use tokio::time::{sleep, sleep_until, timeout, Instant};
 use tokio_serial::SerialStream;
 
-use crate::bark;
-use crate::config::Config;
+use crate::cms;
+use crate::concat::{Assembled, Reassembler};
+use crate::config::{BarkOptions, Config, SharedConfig, SmsMode};
+use crate::history::{self, DeliveryStatus, History};
+use crate::notify::{self, Level, Notification};
+use crate::otp;
+use crate::outbox::{Delivery, Outbox};
+use crate::pdu;
+use crate::quiet::{self, QuietAction};
+use crate::rules;
+use crate::sendqueue::{Due, Job, SendQueue};
 
 const AT_TIMEOUT: Duration = Duration::from_secs(5);
+/// 写入 PDU 后等待 +CMGS 的时间，短信中心确认可能需要数十秒
+const CMGS_TIMEOUT: Duration = Duration::from_secs(30);
 const RESPONSE_READ_TIMEOUT: Duration = Duration::from_secs(10);
+const REOPEN_DELAY: Duration = Duration::from_secs(5);
+/// 空闲时单次等待串口数据的时长，到时后重新等待
+const IDLE_READ_TIMEOUT: Duration = Duration::from_secs(60);
+
+type AtResult<T> = Result<T, Box<dyn std::error::Error + Send + Sync>>;
 
 /// 打开串口
 fn open_port(device: &str, baud_rate: u32) -> Result<SerialStream, tokio_serial::Error> {
@@ -16,127 +36,207 @@ fn open_port(device: &str, baud_rate: u32) -> Result<SerialStream, tokio_serial:
     SerialStream::open(&builder)
 }
 
-/// 发送 AT 指令并读取响应直到 OK/ERROR 或超时
-async fn at_command(
-    port: &mut SerialStream,
-    cmd: &str,
-) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
-    let line = format!("AT{}\r\n", cmd);
-    port.write_all(line.as_bytes()).await?;
-    port.flush().await?;
+/// 发送短信失败的原因
+#[derive(Debug)]
+pub enum SendError {
+    /// 号码或内容不合法，未发送
+    Invalid(String),
+    /// 发送过程中串口读写失败
+    Port(String),
+    /// 等待 +CMGS 响应超时，短信可能已发出
+    Timeout,
+    /// modem 返回 +CMS ERROR；code 为错误码（modem 以文字给出时为 None）
+    Cms { code: Option<u16>, reason: String },
+    /// modem 返回 ERROR、+CME ERROR 或无法识别的响应
+    Modem(String),
+    /// 无法写入发送队列
+    Queue(String),
+    /// 超过 send_max_age_secs 仍未发出
+    Expired,
+}
 
-    let mut buf = Vec::with_capacity(1024);
-    let mut read_buf = [0u8; 256];
-    let deadline = tokio::time::Instant::now() + RESPONSE_READ_TIMEOUT;
+impl SendError {
+    /// 错误类别，用于 API 响应
+    pub fn kind(&self) -> &'static str {
+        match self {
+            SendError::Invalid(_) => "invalid",
+            SendError::Port(_) => "port",
+            SendError::Timeout => "timeout",
+            SendError::Cms { .. } => "cms_error",
+            SendError::Modem(_) => "modem_error",
+            SendError::Queue(_) => "queue",
+            SendError::Expired => "expired",
+        }
+    }
 
-    loop {
-        if tokio::time::Instant::now() > deadline {
-            break;
+    /// 串口中断或网络拥塞等临时错误，稍后重发可能成功
+    pub fn is_transient(&self) -> bool {
+        match self {
+            SendError::Port(_) => true,
+            SendError::Cms { code: Some(code), .. } => cms::is_transient(*code),
+            _ => false,
         }
-        tokio::select! {
-            r = port.read(&mut read_buf) => {
-                let n = r?;
-                if n == 0 {
-                    break;
-                }
-                buf.extend_from_slice(&read_buf[..n]);
-                let s = String::from_utf8_lossy(&buf);
-                if s.contains("OK") || s.contains("ERROR") {
-                    break;
-                }
-            }
-            _ = sleep(Duration::from_millis(100)) => {}
+    }
+}
+
+impl std::fmt::Display for SendError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SendError::Invalid(msg)
+            | SendError::Port(msg)
+            | SendError::Modem(msg)
+            | SendError::Queue(msg) => f.write_str(msg),
+            SendError::Timeout => f.write_str("timed out waiting for +CMGS"),
+            SendError::Expired => f.write_str("not sent before send_max_age_secs"),
+            SendError::Cms { code: Some(code), reason } => write!(f, "+CMS ERROR {}: {}", code, reason),
+            SendError::Cms { code: None, reason } => write!(f, "+CMS ERROR: {}", reason),
         }
     }
+}
 
-    Ok(String::from_utf8_lossy(&buf).into_owned())
+/// 发送成功的短信
+pub struct Sent {
+    /// 短信历史中的发信记录 ID，记录失败时为 None
+    pub id: Option<i64>,
+    /// 分段数
+    pub segments: usize,
+    /// 各段 +CMGS 返回的消息参考号（modem 未给出时为 None）
+    pub message_refs: Vec<Option<u8>>,
 }
 
-/// 发送短信（AT+CMGF=1 文本模式，AT+CMGS 后跟号码，再发正文 + Ctrl+Z）
-pub async fn send_sms(cfg: &Config, number: &str, text: &str) {
-    tracing::info!(number = %number, "sending SMS via AT");
-    let mut port = match open_port(&cfg.modem_device, cfg.baud_rate) {
-        Ok(p) => p,
-        Err(e) => {
-            tracing::error!(err = %e, "open modem port failed");
-            return;
+/// 解析 AT+CMGS 的响应："+CMGS: <mr>" 为成功，+CMS ERROR 解析错误码，其他含 ERROR 的行原样作为错误原因
+fn parse_cmgs_response(resp: &str) -> Result<Option<u8>, SendError> {
+    for line in resp.lines().map(str::trim) {
+        if let Some(mr) = line.strip_prefix("+CMGS:") {
+            return Ok(mr.trim().parse().ok());
         }
-    };
-
-    let run = async {
-        at_command(&mut port, "+CMGF=1").await?;
-        let cmd = format!("+CMGS=\"{}\"", number);
-        port.write_all(format!("AT{}\r\n", cmd).as_bytes()).await?;
-        port.flush().await?;
-        sleep(Duration::from_millis(300)).await;
-        port.write_all(text.as_bytes()).await?;
-        port.write_all(&[0x1A]).await?; // Ctrl+Z
-        port.flush().await?;
-
-        let mut buf = vec![0u8; 512];
-        let mut total = 0usize;
-        let deadline = tokio::time::Instant::now() + RESPONSE_READ_TIMEOUT;
-        while tokio::time::Instant::now() < deadline && total < buf.len() {
-            if let Ok(n) = timeout(Duration::from_millis(200), port.read(&mut buf[total..])).await {
-                if let Ok(sz) = n {
-                    total += sz;
-                    if sz == 0 {
-                        break;
-                    }
-                    let s = String::from_utf8_lossy(&buf[..total]);
-                    if s.contains("OK") || s.contains("ERROR") || s.contains("+CMGS:") {
-                        break;
-                    }
-                }
-            }
+        if let Some((code, reason)) = cms::parse(line) {
+            return Err(SendError::Cms { code, reason });
         }
-        Ok::<(), Box<dyn std::error::Error + Send + Sync>>(())
-    };
-
-    match timeout(AT_TIMEOUT, run).await {
-        Ok(Ok(())) => tracing::info!(number = %number, "发送成功"),
-        Ok(Err(e)) => tracing::warn!(err = %e, "AT send_sms failed"),
-        Err(_) => tracing::warn!("send_sms timeout"),
+        if line.contains("ERROR") {
+            return Err(SendError::Modem(line.to_string()));
+        }
+    }
+    if resp.contains("OK") {
+        Ok(None)
+    } else {
+        Err(SendError::Timeout)
     }
 }
 
 /// 一条短信（索引、号码、正文）
 struct SmsEntry {
-    index: u32,
+    /// 存储索引；+CMT 直接上报的短信不在 SIM 上，没有索引
+    index: Option<u32>,
     number: String,
     body: String,
+    /// 服务中心时间戳（SCTS）
+    timestamp: Option<DateTime<FixedOffset>>,
+    /// 长短信分段信息（仅 PDU 模式可得）
+    concat: Option<pdu::Concat>,
+    /// 原始内容：PDU 十六进制，或文本模式下 modem 给出的正文
+    raw: String,
+}
+
+impl SmsEntry {
+    /// 文本模式：header 为 +CMGL/+CMGR/+CMT 的参数，号码位于 number_at，其后隔一个 <alpha> 为时间戳。
+    /// 部分 modem 在 UCS2 字符集下正文会给出十六进制串，此处尝试还原
+    fn from_text(index: Option<u32>, header: &[String], number_at: usize, body: &str) -> Self {
+        let body = body.trim();
+        Self {
+            index,
+            number: header.get(number_at).cloned().unwrap_or_default(),
+            body: decode_ucs2_hex(body).unwrap_or_else(|| body.to_string()),
+            timestamp: header.get(number_at + 2).and_then(|s| parse_text_timestamp(s)),
+            concat: None,
+            raw: body.to_string(),
+        }
+    }
+
+    /// PDU 模式下的正文：解码 SMS-DELIVER，失败时记录日志并跳过，不影响同一列表中的其他短信
+    fn from_pdu(index: Option<u32>, hex: &str) -> Option<Self> {
+        match pdu::decode_deliver(hex) {
+            Ok(d) => {
+                tracing::debug!(
+                    index = ?index,
+                    smsc = ?d.smsc,
+                    dcs = d.dcs,
+                    scts = ?d.timestamp,
+                    udh = ?d.udh.iter().map(|ie| (ie.id, ie.data.len())).collect::<Vec<_>>(),
+                    "decoded SMS-DELIVER PDU"
+                );
+                Some(Self {
+                    index,
+                    timestamp: d.timestamp,
+                    concat: d.concat(),
+                    number: d.sender,
+                    body: d.text,
+                    raw: hex.to_string(),
+                })
+            }
+            Err(e) => {
+                tracing::warn!(index = ?index, err = %e, "decode PDU failed");
+                None
+            }
+        }
+    }
+}
+
+/// 解析文本模式时间戳 "yy/MM/dd,hh:mm:ss±zz"（zz 以 15 分钟为单位）
+fn parse_text_timestamp(s: &str) -> Option<DateTime<FixedOffset>> {
+    let (local, zone) = s.split_at_checked(17)?;
+    let quarters: i32 = zone.parse().ok()?;
+    NaiveDateTime::parse_from_str(local, "%y/%m/%d,%H:%M:%S")
+        .ok()?
+        .and_local_timezone(FixedOffset::east_opt(quarters * 15 * 60)?)
+        .single()
+}
+
+/// 按逗号切分 AT 响应参数，引号内的逗号（如时间戳）不切分
+fn split_fields(s: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut cur = String::new();
+    let mut quoted = false;
+    for c in s.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            ',' if !quoted => {
+                fields.push(cur.trim().to_string());
+                cur.clear();
+            }
+            _ => cur.push(c),
+        }
+    }
+    fields.push(cur.trim().to_string());
+    fields
 }
 
 /// 解析 AT+CMGL 输出，提取 +CMGL: 行及后续正文
 fn parse_cmgl_response(response: &str) -> Vec<SmsEntry> {
     let mut list = Vec::new();
-    let mut lines = response.lines();
     let mut current_index = 0u32;
-    let mut current_number = String::new();
+    let mut current_header = Vec::new();
     let mut current_body = String::new();
     let mut in_body = false;
 
-    while let Some(line) = lines.next() {
+    for line in response.lines() {
         let line = line.trim();
-        if line.starts_with("+CMGL:") {
+        if let Some(rest) = line.strip_prefix("+CMGL:") {
             if in_body && !current_body.is_empty() {
-                list.push(SmsEntry {
-                    index: current_index,
-                    number: current_number.clone(),
-                    body: current_body.trim().to_string(),
-                });
+                list.push(SmsEntry::from_text(
+                    Some(current_index),
+                    &current_header,
+                    2,
+                    &current_body,
+                ));
             }
             in_body = true;
             current_body.clear();
             // +CMGL: <index>,<stat>,"<number>","","<date>" 或类似
-            let rest = line.strip_prefix("+CMGL:").unwrap_or("").trim();
-            let parts: Vec<&str> = rest.split(',').map(|s| s.trim()).collect();
-            if let Some(idx) = parts.get(0).and_then(|s| s.parse::<u32>().ok()) {
+            current_header = split_fields(rest);
+            if let Some(idx) = current_header.first().and_then(|s| s.parse::<u32>().ok()) {
                 current_index = idx;
             }
-            if let Some(num) = parts.get(2) {
-                current_number = num.trim_matches('"').to_string();
-            }
         } else if in_body && !line.is_empty() && !line.eq_ignore_ascii_case("OK") {
             if !current_body.is_empty() {
                 current_body.push('\n');
@@ -145,69 +245,947 @@ fn parse_cmgl_response(response: &str) -> Vec<SmsEntry> {
         }
     }
     if in_body && !current_body.is_empty() {
-        list.push(SmsEntry {
-            index: current_index,
-            number: current_number.clone(),
-            body: current_body.trim().to_string(),
-        });
+        list.push(SmsEntry::from_text(
+            Some(current_index),
+            &current_header,
+            2,
+            &current_body,
+        ));
     }
     list
 }
 
-pub async fn start(cfg: Config) {
-    tracing::info!("SMS poll loop started (AT), interval 5s, device {}", cfg.modem_device);
-    loop {
-        let mut port = match open_port(&cfg.modem_device, cfg.baud_rate) {
-            Ok(p) => p,
-            Err(e) => {
-                tracing::error!(err = %e, "open modem port failed");
-                sleep(Duration::from_secs(5)).await;
+/// 解析 AT+CMGR=<index> 输出：+CMGR: <stat>,"<number>","","<date>" 后跟正文
+fn parse_cmgr_response(index: u32, response: &str) -> Option<SmsEntry> {
+    let mut header = None;
+    let mut body = String::new();
+    for line in response.lines() {
+        let line = line.trim();
+        if let Some(rest) = line.strip_prefix("+CMGR:") {
+            header = Some(split_fields(rest));
+        } else if header.is_some() && !line.is_empty() && !line.eq_ignore_ascii_case("OK") {
+            if !body.is_empty() {
+                body.push('\n');
+            }
+            body.push_str(line);
+        }
+    }
+    Some(SmsEntry::from_text(Some(index), &header?, 1, &body))
+}
+
+/// 解析 AT+CMGL=4 输出（PDU 模式）：+CMGL: <index>,<stat>,[<alpha>],<length> 后跟一行 PDU
+fn parse_cmgl_pdu_response(response: &str) -> Vec<SmsEntry> {
+    let mut list = Vec::new();
+    let mut current_index = None;
+    for line in response.lines() {
+        let line = line.trim();
+        if let Some(rest) = line.strip_prefix("+CMGL:") {
+            current_index = split_fields(rest).first().and_then(|s| s.parse::<u32>().ok());
+        } else if !line.is_empty() {
+            if let Some(index) = current_index.take() {
+                list.extend(SmsEntry::from_pdu(Some(index), line));
+            }
+        }
+    }
+    list
+}
+
+/// 解析 AT+CMGR=<index> 输出（PDU 模式）：+CMGR: <stat>,[<alpha>],<length> 后跟一行 PDU
+fn parse_cmgr_pdu_response(index: u32, response: &str) -> Option<SmsEntry> {
+    let mut lines = response.lines().map(str::trim);
+    lines.find(|l| l.starts_with("+CMGR:"))?;
+    let hex = lines.find(|l| !l.is_empty())?;
+    SmsEntry::from_pdu(Some(index), hex)
+}
+
+fn parse_list(mode: SmsMode, response: &str) -> Vec<SmsEntry> {
+    match mode {
+        SmsMode::Text => parse_cmgl_response(response),
+        SmsMode::Pdu => parse_cmgl_pdu_response(response),
+    }
+}
+
+fn parse_read(mode: SmsMode, index: u32, response: &str) -> Option<SmsEntry> {
+    match mode {
+        SmsMode::Text => parse_cmgr_response(index, response),
+        SmsMode::Pdu => parse_cmgr_pdu_response(index, response),
+    }
+}
+
+/// 解析 +CMT 上报：文本模式头部为 "<number>",...，PDU 模式头部为 [<alpha>],<length>，正文为 PDU
+fn parse_cmt(mode: SmsMode, header: &str, body: &str) -> Option<SmsEntry> {
+    match mode {
+        SmsMode::Text => Some(SmsEntry::from_text(None, &split_fields(header), 0, body)),
+        SmsMode::Pdu => SmsEntry::from_pdu(None, body),
+    }
+}
+
+/// 文本模式状态报告的字段：<fo>,<mr>,[<ra>],[<tora>],<scts>,<dt>,<st>
+fn parse_text_report(fields: &[String]) -> Option<pdu::StatusReport> {
+    Some(pdu::StatusReport {
+        message_ref: fields.get(1)?.parse().ok()?,
+        recipient: fields.get(2).cloned().unwrap_or_default(),
+        discharged: fields.get(5).and_then(|s| parse_text_timestamp(s)),
+        status: fields.get(6)?.parse().ok()?,
+    })
+}
+
+/// 解码状态报告 PDU，失败时记录日志
+fn decode_report_pdu(hex: &str) -> Option<pdu::StatusReport> {
+    pdu::decode_status_report(hex)
+        .inspect_err(|e| tracing::warn!(err = %e, "decode status report PDU failed"))
+        .ok()
+}
+
+/// 解析 +CDS 上报
+fn parse_cds(header: &str, body: Option<&str>) -> Option<pdu::StatusReport> {
+    match body {
+        Some(hex) => decode_report_pdu(hex),
+        None => parse_text_report(&split_fields(header)),
+    }
+}
+
+/// 解析读取已存储状态报告的 AT+CMGR 输出：PDU 模式为 +CMGR: <stat>,[<alpha>],<length> 后跟一行 PDU，
+/// 文本模式为 +CMGR: <stat>,<fo>,<mr>,[<ra>],[<tora>],<scts>,<dt>,<st>
+fn parse_cmgr_report(mode: SmsMode, response: &str) -> Option<pdu::StatusReport> {
+    let mut lines = response.lines().map(str::trim);
+    let header = lines.find(|l| l.starts_with("+CMGR:"))?;
+    match mode {
+        SmsMode::Pdu => decode_report_pdu(lines.find(|l| !l.is_empty())?),
+        SmsMode::Text => parse_text_report(&split_fields(&header["+CMGR:".len()..])[1..]),
+    }
+}
+
+/// 当前模式对应的 AT+CMGF 参数
+fn cmgf(mode: SmsMode) -> &'static str {
+    match mode {
+        SmsMode::Text => "+CMGF=1",
+        SmsMode::Pdu => "+CMGF=0",
+    }
+}
+
+/// 主动上报（URC）
+enum Urc {
+    /// +CMTI: "<mem>",<index>：新短信已存入指定存储
+    Stored { mem: String, index: u32 },
+    /// +CMT: ...：短信直接上报、未存储（后跟一行正文或 PDU）
+    Delivered { header: String, body: String },
+    /// +CDS: ...：状态报告直接上报（PDU 模式后跟一行 PDU，文本模式只有这一行）
+    Report { header: String, body: Option<String> },
+    /// +CDSI: "<mem>",<index>：状态报告已存入指定存储
+    ReportStored { mem: String, index: u32 },
+}
+
+/// 解析 +CMTI / +CDSI 的 "<mem>",<index>
+fn parse_stored(rest: &str) -> Option<(String, u32)> {
+    let fields = split_fields(rest);
+    let index = fields.get(1)?.parse().ok()?;
+    Some((fields[0].clone(), index))
+}
+
+/// 最终结果码
+fn is_final(line: &str) -> bool {
+    line == "OK" || line == "ERROR" || line.starts_with("+CMS ERROR") || line.starts_with("+CME ERROR")
+}
+
+/// 等待 AT+CMGS 提示符的结果
+enum Prompt {
+    Ready,
+    /// modem 直接返回了最终结果码
+    Rejected(String),
+    Timeout,
+}
+
+/// 长连接串口会话：命令执行期间收到的 URC 暂存在队列中，待空闲时处理
+struct Session {
+    port: SerialStream,
+    buf: Vec<u8>,
+    urcs: VecDeque<Urc>,
+}
+
+impl Session {
+    fn open(device: &str, baud_rate: u32) -> Result<Self, tokio_serial::Error> {
+        Ok(Self {
+            port: open_port(device, baud_rate)?,
+            buf: Vec::with_capacity(1024),
+            urcs: VecDeque::new(),
+        })
+    }
+
+    /// 从串口再读一批数据到缓冲；deadline 前没有数据则返回 false
+    async fn fill(&mut self, deadline: Instant) -> AtResult<bool> {
+        let mut read_buf = [0u8; 256];
+        loop {
+            let now = Instant::now();
+            if now >= deadline {
+                return Ok(false);
+            }
+            match timeout(deadline - now, self.port.read(&mut read_buf)).await {
+                Ok(Ok(0)) => {
+                    return Err(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "serial port closed").into())
+                }
+                Ok(Ok(n)) => {
+                    self.buf.extend_from_slice(&read_buf[..n]);
+                    return Ok(true);
+                }
+                Ok(Err(e)) if e.kind() == std::io::ErrorKind::TimedOut => {}
+                Ok(Err(e)) => return Err(e.into()),
+                Err(_) => return Ok(false),
+            }
+        }
+    }
+
+    /// 取出缓冲中完整的一行（去掉首尾空白）
+    fn take_line(&mut self) -> Option<String> {
+        let pos = self.buf.iter().position(|&b| b == b'\n')?;
+        let line: Vec<u8> = self.buf.drain(..=pos).collect();
+        Some(String::from_utf8_lossy(&line).trim().to_string())
+    }
+
+    /// 读取一行；deadline 前没有完整一行则返回 None
+    async fn read_line(&mut self, deadline: Instant) -> AtResult<Option<String>> {
+        loop {
+            if let Some(line) = self.take_line() {
+                return Ok(Some(line));
+            }
+            if !self.fill(deadline).await? {
+                return Ok(None);
+            }
+        }
+    }
+
+    async fn write(&mut self, data: &str) -> AtResult<()> {
+        self.port.write_all(data.as_bytes()).await?;
+        self.port.flush().await?;
+        Ok(())
+    }
+
+    /// 若该行是 URC 则解析（+CMT 需再读一行正文）
+    async fn parse_urc(&mut self, line: &str) -> AtResult<Option<Urc>> {
+        if let Some(rest) = line.strip_prefix("+CMTI:") {
+            return Ok(parse_stored(rest).map(|(mem, index)| Urc::Stored { mem, index }));
+        }
+        if let Some(rest) = line.strip_prefix("+CDSI:") {
+            return Ok(parse_stored(rest).map(|(mem, index)| Urc::ReportStored { mem, index }));
+        }
+        if let Some(rest) = line.strip_prefix("+CDS:") {
+            let header = rest.trim().to_string();
+            // PDU 模式的头部只有 PDU 长度一个字段
+            let body = if split_fields(&header).len() == 1 {
+                Some(
+                    self.read_line(Instant::now() + RESPONSE_READ_TIMEOUT)
+                        .await?
+                        .unwrap_or_default(),
+                )
+            } else {
+                None
+            };
+            return Ok(Some(Urc::Report { header, body }));
+        }
+        if let Some(rest) = line.strip_prefix("+CMT:") {
+            let header = rest.trim().to_string();
+            let body = self
+                .read_line(Instant::now() + RESPONSE_READ_TIMEOUT)
+                .await?
+                .unwrap_or_default();
+            return Ok(Some(Urc::Delivered { header, body }));
+        }
+        Ok(None)
+    }
+
+    /// 读取响应直到最终结果码（OK/ERROR/+CMS ERROR/+CME ERROR），期间的 URC 放入队列；超时返回 None
+    async fn response(&mut self, deadline: Instant) -> AtResult<Option<String>> {
+        let mut out = String::new();
+        while let Some(line) = self.read_line(deadline).await? {
+            if let Some(urc) = self.parse_urc(&line).await? {
+                self.urcs.push_back(urc);
                 continue;
             }
-        };
+            out.push_str(&line);
+            out.push('\n');
+            if is_final(&line) {
+                return Ok(Some(out));
+            }
+        }
+        Ok(None)
+    }
+
+    /// 发送 AT 指令并读取响应，最终结果码不是 OK 时返回 Err
+    async fn command(&mut self, cmd: &str) -> AtResult<String> {
+        self.write(&format!("AT{}\r\n", cmd)).await?;
+        match self.response(Instant::now() + RESPONSE_READ_TIMEOUT).await? {
+            Some(out) if out.lines().last() == Some("OK") => Ok(out),
+            Some(out) => Err(format!("AT{} failed: {}", cmd, out.lines().last().unwrap_or_default()).into()),
+            None => Err(format!("AT{} timeout", cmd).into()),
+        }
+    }
+
+    /// 等待 AT+CMGS 的 "> " 提示符（其后没有换行）；先收到最终结果码时返回该响应
+    async fn wait_prompt(&mut self, deadline: Instant) -> AtResult<Prompt> {
+        loop {
+            while let Some(line) = self.take_line() {
+                if let Some(urc) = self.parse_urc(&line).await? {
+                    self.urcs.push_back(urc);
+                } else if is_final(&line) {
+                    return Ok(Prompt::Rejected(line));
+                }
+            }
+            if self.buf.trim_ascii_start().starts_with(b">") {
+                self.buf.clear();
+                return Ok(Prompt::Ready);
+            }
+            if !self.fill(deadline).await? {
+                return Ok(Prompt::Timeout);
+            }
+        }
+    }
+
+    /// 发送一段 PDU：AT+CMGS=<length>，等到提示符后写入十六进制 PDU 与 Ctrl+Z。
+    /// 串口读写失败时返回外层 Err，modem 给出的结果在内层
+    async fn submit(&mut self, part: &pdu::Submit) -> AtResult<Result<Option<u8>, SendError>> {
+        self.write(&format!("AT+CMGS={}\r", part.length)).await?;
+        match self.wait_prompt(Instant::now() + AT_TIMEOUT).await? {
+            Prompt::Ready => {}
+            Prompt::Rejected(resp) => return Ok(parse_cmgs_response(&resp)),
+            Prompt::Timeout => {
+                // 没有提示符：modem 可能仍在等待输入，发送 ESC 取消
+                self.write("\x1b").await?;
+                return Ok(Err(SendError::Modem("no > prompt after AT+CMGS".to_string())));
+            }
+        }
+        self.write(&format!("{}\x1a", part.hex)).await?; // Ctrl+Z
+        Ok(match self.response(Instant::now() + CMGS_TIMEOUT).await? {
+            Some(resp) => parse_cmgs_response(&resp),
+            None => Err(SendError::Timeout),
+        })
+    }
+
+    /// 把缓冲中的完整行解析为 URC 放入队列，其他行忽略
+    async fn collect_urcs(&mut self) -> AtResult<()> {
+        while let Some(line) = self.take_line() {
+            if let Some(urc) = self.parse_urc(&line).await? {
+                self.urcs.push_back(urc);
+            } else if !line.is_empty() {
+                tracing::debug!(line = %line, "ignored unsolicited line");
+            }
+        }
+        Ok(())
+    }
 
-        let list_result = async {
-            at_command(&mut port, "+CMGF=1").await?;
-            let out = at_command(&mut port, "+CMGL=\"ALL\"").await?;
-            Ok::<_, Box<dyn std::error::Error + Send + Sync>>(out)
+    /// 初始化 modem 后依次执行命令，并把 URC 转为事件交给收信任务；
+    /// 串口相关配置变更时返回 Ok，串口读写失败时返回 Err
+    async fn run(
+        mut self,
+        cfg: &SharedConfig,
+        opened: Arc<Config>,
+        requests: &mut mpsc::Receiver<Request>,
+        events: &mpsc::UnboundedSender<Event>,
+    ) -> AtResult<()> {
+        let mut changes = cfg.subscribe();
+        let mode = opened.sms_mode;
+        self.command("E0").await?;
+        self.command(cmgf(mode)).await?;
+        // 新短信存储后以 +CMTI 上报索引；状态报告优先直接以 +CDS 上报，modem 不支持时改为存储后以 +CDSI 上报
+        let cnmi: &[&str] = if opened.delivery_reports {
+            &["+CNMI=2,1,0,1,0", "+CNMI=2,1,0,2,0", "+CNMI=2,1,0,0,0"]
+        } else {
+            &["+CNMI=2,1,0,0,0"]
         };
+        for (i, cmd) in cnmi.iter().enumerate() {
+            match self.command(cmd).await {
+                Ok(_) => break,
+                Err(e) if i + 1 < cnmi.len() => {
+                    tracing::warn!(cmd = %cmd, err = %e, "modem rejected AT+CNMI, trying next setting")
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        tracing::info!("modem session ready, waiting for +CMTI");
+        let _ = events.send(Event::Ready);
 
-        let response = match timeout(AT_TIMEOUT, list_result).await {
-            Ok(Ok(s)) => s,
-            Ok(Err(e)) => {
-                tracing::warn!(err = %e, "AT list SMS failed");
-                sleep(Duration::from_secs(5)).await;
-                continue;
+        loop {
+            // 命令响应之后可能已读到 URC
+            self.collect_urcs().await?;
+            while let Some(urc) = self.urcs.pop_front() {
+                if let Some(event) = to_event(mode, urc) {
+                    let _ = events.send(event);
+                }
             }
-            Err(_) => {
-                tracing::warn!("AT list SMS timeout");
-                sleep(Duration::from_secs(5)).await;
-                continue;
+            // 串口读取在收到数据前可安全取消，已读到的数据留在缓冲中
+            tokio::select! {
+                filled = self.fill(Instant::now() + IDLE_READ_TIMEOUT) => {
+                    filled?;
+                    self.collect_urcs().await?;
+                }
+                req = requests.recv() => match req {
+                    Some(req) => self.execute(mode, req).await?,
+                    None => return Ok(()),
+                },
+                Ok(()) = changes.changed() => {
+                    let current = cfg.get();
+                    if needs_reopen(&opened, &current) {
+                        tracing::info!(device = %current.modem_device, "modem settings changed, reopening port");
+                        return Ok(());
+                    }
+                }
+            }
+        }
+    }
+
+    /// 执行一条命令并回复请求方；串口读写失败时返回 Err 结束会话，modem 返回的错误只交给请求方
+    async fn execute(&mut self, mode: SmsMode, req: Request) -> AtResult<()> {
+        match req {
+            Request::List(reply) => {
+                let cmd = match mode {
+                    SmsMode::Text => "+CMGL=\"ALL\"",
+                    SmsMode::Pdu => "+CMGL=4",
+                };
+                let result = self.command(cmd).await;
+                answer(reply, result.map(|resp| parse_list(mode, &resp)))
             }
+            Request::Read(index, reply) => {
+                let result = self.command(&format!("+CMGR={}", index)).await;
+                answer(reply, result.map(|resp| parse_read(mode, index, &resp)))
+            }
+            Request::ReadReport(index, reply) => {
+                let result = self.command(&format!("+CMGR={}", index)).await;
+                answer(reply, result.map(|resp| parse_cmgr_report(mode, &resp)))
+            }
+            Request::Delete(index, reply) => {
+                let result = self.command(&format!("+CMGD={}", index)).await;
+                answer(reply, result.map(drop))
+            }
+            Request::At(cmd, reply) => {
+                let result = self.command(&cmd).await;
+                answer(reply, result)
+            }
+            Request::Send { parts, skip, reply } => {
+                let mut message_refs = Vec::new();
+                let result = self.submit_all(&parts, skip, &mut message_refs).await;
+                let broken = match &result {
+                    Err(SendError::Port(e)) => Some(e.clone()),
+                    _ => None,
+                };
+                let _ = reply.send(Submitted {
+                    message_refs,
+                    result,
+                });
+                if let Some(e) = broken {
+                    return Err(e.into());
+                }
+                // AT+CMGF 是 modem 全局设置，发送完（无论成败）恢复为收信使用的模式
+                if mode == SmsMode::Text {
+                    if let Err(e) = self.command(cmgf(mode)).await {
+                        if e.is::<std::io::Error>() {
+                            return Err(e);
+                        }
+                        tracing::warn!(err = %e, "restore AT+CMGF failed");
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// 切换到 PDU 模式后从第 skip 段起逐段发送；任一段失败即停止，串口读写失败记为 SendError::Port
+    async fn submit_all(
+        &mut self,
+        parts: &[pdu::Submit],
+        skip: usize,
+        message_refs: &mut Vec<Option<u8>>,
+    ) -> Result<(), SendError> {
+        if let Err(e) = self.command("+CMGF=0").await {
+            return Err(if e.is::<std::io::Error>() {
+                SendError::Port(e.to_string())
+            } else {
+                SendError::Modem(e.to_string())
+            });
+        }
+        for (i, part) in parts.iter().enumerate().skip(skip) {
+            let result = self
+                .submit(part)
+                .await
+                .map_err(|e| SendError::Port(e.to_string()))?;
+            match result {
+                Ok(mr) => message_refs.push(mr),
+                Err(e) => {
+                    tracing::warn!(segment = i + 1, segments = parts.len(), err = %e, "send segment failed");
+                    return Err(e);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 回复请求方；串口读写失败时同时返回 Err 以结束会话
+fn answer<T>(reply: Reply<T>, result: AtResult<T>) -> AtResult<()> {
+    let broken = match &result {
+        Err(e) if e.is::<std::io::Error>() => Some(e.to_string()),
+        _ => None,
+    };
+    let _ = reply.send(result);
+    broken.map_or(Ok(()), |e| Err(e.into()))
+}
+
+/// 串口任务上报给收信任务的事件
+enum Event {
+    /// 串口已打开并完成初始化
+    Ready,
+    /// 串口已关闭，稍后重新打开
+    Closed,
+    /// +CMTI：新短信已存入指定存储
+    Stored { mem: String, index: u32 },
+    /// +CMT：直接上报、未存储的短信
+    Delivered(SmsEntry),
+    /// +CDS：直接上报的状态报告
+    Report(pdu::StatusReport),
+    /// +CDSI：状态报告已存入指定存储
+    ReportStored { mem: String, index: u32 },
+}
+
+/// 把 URC 转为事件；+CMT 与 +CDS 在这里解析，无法解析时记录日志并丢弃
+fn to_event(mode: SmsMode, urc: Urc) -> Option<Event> {
+    match urc {
+        Urc::Stored { mem, index } => Some(Event::Stored { mem, index }),
+        Urc::Delivered { header, body } => {
+            let ent = parse_cmt(mode, &header, &body);
+            if ent.is_none() {
+                tracing::warn!(header = %header, "unparsable +CMT");
+            }
+            ent.map(Event::Delivered)
+        }
+        Urc::Report { header, body } => {
+            let report = parse_cds(&header, body.as_deref());
+            if report.is_none() {
+                tracing::warn!(header = %header, "unparsable +CDS");
+            }
+            report.map(Event::Report)
+        }
+        Urc::ReportStored { mem, index } => Some(Event::ReportStored { mem, index }),
+    }
+}
+
+type Reply<T> = oneshot::Sender<AtResult<T>>;
+
+/// 发给串口任务的命令
+enum Request {
+    /// 列出存储中的全部短信
+    List(Reply<Vec<SmsEntry>>),
+    /// 读取一条短信
+    Read(u32, Reply<Option<SmsEntry>>),
+    /// 读取一条已存储的状态报告
+    ReadReport(u32, Reply<Option<pdu::StatusReport>>),
+    Delete(u32, Reply<()>),
+    /// 从第 skip 段起逐段发送 SMS-SUBMIT PDU
+    Send {
+        parts: Vec<pdu::Submit>,
+        skip: usize,
+        reply: oneshot::Sender<Submitted>,
+    },
+    /// 原样执行一条 AT 指令（不含 "AT" 前缀）
+    At(String, Reply<String>),
+}
+
+impl Request {
+    /// 串口未打开时直接回复错误
+    fn reject(self, reason: &str) {
+        match self {
+            Request::List(reply) => drop(reply.send(Err(reason.into()))),
+            Request::Read(_, reply) => drop(reply.send(Err(reason.into()))),
+            Request::ReadReport(_, reply) => drop(reply.send(Err(reason.into()))),
+            Request::Delete(_, reply) => drop(reply.send(Err(reason.into()))),
+            Request::At(_, reply) => drop(reply.send(Err(reason.into()))),
+            Request::Send { reply, .. } => drop(reply.send(Submitted {
+                message_refs: Vec::new(),
+                result: Err(SendError::Port(reason.to_string())),
+            })),
+        }
+    }
+}
+
+/// 一次发送的结果：本次发出分段的消息参考号，以及失败原因
+struct Submitted {
+    message_refs: Vec<Option<u8>>,
+    result: Result<(), SendError>,
+}
+
+/// 串口任务的句柄：其他模块只通过它访问 modem，所有 AT 指令由串口任务依次执行
+#[derive(Clone)]
+pub struct Modem {
+    tx: mpsc::Sender<Request>,
+}
+
+impl Modem {
+    async fn request<T>(&self, make: impl FnOnce(Reply<T>) -> Request) -> AtResult<T> {
+        let (tx, rx) = oneshot::channel();
+        self.tx.send(make(tx)).await.map_err(|_| "modem task stopped")?;
+        rx.await.map_err(|_| "modem task stopped")?
+    }
+
+    async fn list(&self) -> AtResult<Vec<SmsEntry>> {
+        self.request(Request::List).await
+    }
+
+    async fn read(&self, index: u32) -> AtResult<Option<SmsEntry>> {
+        self.request(|tx| Request::Read(index, tx)).await
+    }
+
+    async fn read_report(&self, index: u32) -> AtResult<Option<pdu::StatusReport>> {
+        self.request(|tx| Request::ReadReport(index, tx)).await
+    }
+
+    async fn delete(&self, index: u32) -> AtResult<()> {
+        self.request(|tx| Request::Delete(index, tx)).await
+    }
+
+    async fn send(&self, parts: Vec<pdu::Submit>, skip: usize) -> Submitted {
+        let stopped = || Submitted {
+            message_refs: Vec::new(),
+            result: Err(SendError::Port("modem task stopped".to_string())),
         };
+        let (reply, rx) = oneshot::channel();
+        if self.tx.send(Request::Send { parts, skip, reply }).await.is_err() {
+            return stopped();
+        }
+        rx.await.unwrap_or_else(|_| stopped())
+    }
+
+    /// 原样执行一条 AT 指令（不含 "AT" 前缀），返回 modem 的响应；目前没有调用方，
+    /// 需要执行其他 AT 指令的功能应通过这里排队，不要另开串口
+    #[allow(dead_code)]
+    pub async fn at(&self, cmd: &str) -> AtResult<String> {
+        self.request(|tx| Request::At(cmd.to_string(), tx)).await
+    }
+}
+
+/// 串口任务：持有串口长连接；串口出错或串口相关配置变更时关闭并按最新配置重新打开
+async fn serve(
+    cfg: SharedConfig,
+    mut requests: mpsc::Receiver<Request>,
+    events: mpsc::UnboundedSender<Event>,
+) {
+    loop {
+        let current = cfg.get();
+        match Session::open(&current.modem_device, current.baud_rate) {
+            Ok(session) => {
+                tracing::info!(device = %current.modem_device, baud_rate = current.baud_rate, "modem port opened");
+                let result = session.run(&cfg, current, &mut requests, &events).await;
+                let _ = events.send(Event::Closed);
+                if requests.is_closed() {
+                    return;
+                }
+                match result {
+                    // 配置变更，立即按新配置重新打开
+                    Ok(()) => continue,
+                    Err(e) => tracing::warn!(err = %e, "modem session ended, reopening"),
+                }
+            }
+            Err(e) => {
+                tracing::error!(device = %current.modem_device, err = %e, "open modem port failed")
+            }
+        }
+        // 等待重开期间收到的命令直接返回错误
+        let reopen = sleep(REOPEN_DELAY);
+        tokio::pin!(reopen);
+        loop {
+            tokio::select! {
+                _ = &mut reopen => break,
+                req = requests.recv() => match req {
+                    Some(req) => req.reject("modem port is not open"),
+                    None => return,
+                },
+            }
+        }
+    }
+}
 
-        let entries = parse_cmgl_response(&response);
-        for ent in entries {
-            tracing::info!(index = ent.index, number = %ent.number, "轮询到短信");
-            handle_sms(&cfg, ent.number.clone(), ent.body.clone()).await;
+/// 启动串口任务与收信任务，返回串口任务的句柄。收信任务依靠 +CMTI/+CMT 上报实时收信，
+/// 定期 AT+CMGL 兜底，并发出发送队列中的短信
+pub fn start(cfg: SharedConfig, outbox: Outbox, history: History, queue: SendQueue) -> Modem {
+    let initial = cfg.get();
+    tracing::info!(
+        "SMS session started (AT), device {}, fallback sweep every {}s",
+        initial.modem_device,
+        initial.poll_interval_secs
+    );
+    let (tx, requests) = mpsc::channel(16);
+    let (events_tx, events) = mpsc::unbounded_channel();
+    tokio::spawn(serve(cfg.clone(), requests, events_tx));
+    let modem = Modem { tx };
+    // 放在串口任务外面，串口重连不丢已收到的分段
+    let fwd = Forwarder {
+        reassembler: Reassembler::new(Duration::from_secs(initial.concat_timeout_secs)),
+        cfg,
+        outbox,
+        history,
+        queue,
+        modem: modem.clone(),
+    };
+    tokio::spawn(fwd.run(events));
+    modem
+}
+
+/// 收信任务的状态：配置、通知发件箱、短信历史、长短信重组缓冲、发送队列与串口句柄
+struct Forwarder {
+    cfg: SharedConfig,
+    outbox: Outbox,
+    history: History,
+    reassembler: Reassembler,
+    queue: SendQueue,
+    modem: Modem,
+}
 
-            let idx = ent.index;
-            let delete_result = async {
-                at_command(&mut port, &format!("+CMGD={}", idx)).await
+/// 变更后需要重新打开串口的配置项
+fn needs_reopen(opened: &Config, current: &Config) -> bool {
+    opened.modem_device != current.modem_device
+        || opened.baud_rate != current.baud_rate
+        || opened.sms_mode != current.sms_mode
+        || opened.delivery_reports != current.delivery_reports
+}
+
+impl Forwarder {
+    /// 处理串口任务的事件；串口就绪后先兜底轮询一次，串口未就绪时不轮询也不发送
+    async fn run(mut self, mut events: mpsc::UnboundedReceiver<Event>) {
+        let mut changes = self.cfg.subscribe();
+        let mut ready = false;
+        let mut last_sweep: Option<Instant> = None;
+        loop {
+            let cfg = self.cfg.get();
+            self.reassembler
+                .set_timeout(Duration::from_secs(cfg.concat_timeout_secs));
+            let interval = Duration::from_secs(cfg.poll_interval_secs.max(1));
+            if ready && last_sweep.is_none_or(|t| Instant::now() >= t + interval) {
+                if let Err(e) = self.sweep().await {
+                    tracing::warn!(err = %e, "sweep failed");
+                }
+                last_sweep = Some(Instant::now());
+            }
+            let next_sweep = last_sweep.map_or_else(|| Instant::now() + interval, |t| t + interval);
+            for msg in self.reassembler.take_expired() {
+                self.forward_and_delete(msg).await;
+            }
+            let mut deadline = self
+                .reassembler
+                .next_deadline()
+                .map_or(next_sweep, |d| d.min(next_sweep));
+            if ready {
+                loop {
+                    match self.queue.next_due(&cfg) {
+                        Due::Job(job) => self.send_job(&cfg, job).await,
+                        Due::At(t) => {
+                            let wait = (t - chrono::Utc::now()).to_std().unwrap_or_default();
+                            deadline = deadline.min(Instant::now() + wait);
+                            break;
+                        }
+                        Due::Idle => break,
+                    }
+                }
+            }
+            // 配置变更或有新短信要发送时打断等待，回到循环开头处理
+            let event = tokio::select! {
+                event = events.recv() => match event {
+                    Some(event) => event,
+                    None => return,
+                },
+                _ = sleep_until(deadline) => continue,
+                Ok(()) = changes.changed() => continue,
+                _ = self.queue.notified() => continue,
             };
-            if let Ok(Ok(_)) = timeout(Duration::from_secs(2), delete_result).await {
-                tracing::debug!(index = idx, "deleted");
+            match event {
+                Event::Ready => {
+                    ready = true;
+                    last_sweep = None;
+                }
+                Event::Closed => ready = false,
+                Event::Stored { mem, index } => {
+                    tracing::info!(mem = %mem, index = index, "收到新短信通知");
+                    match self.modem.read(index).await {
+                        Ok(Some(ent)) => self.dispatch(ent).await,
+                        Ok(None) => tracing::warn!(index = index, "AT+CMGR returned no message"),
+                        Err(e) => tracing::warn!(index = index, err = %e, "read SMS failed"),
+                    }
+                }
+                Event::Delivered(ent) => {
+                    tracing::info!(number = %ent.number, "收到直接上报短信");
+                    self.dispatch(ent).await;
+                }
+                Event::Report(report) => self.status_report(report),
+                Event::ReportStored { mem, index } => {
+                    tracing::info!(mem = %mem, index = index, "收到状态报告通知");
+                    match self.modem.read_report(index).await {
+                        Ok(Some(report)) => self.status_report(report),
+                        Ok(None) => tracing::warn!(index = index, "AT+CMGR returned no status report"),
+                        Err(e) => tracing::warn!(index = index, err = %e, "read status report failed"),
+                    }
+                    if let Err(e) = self.modem.delete(index).await {
+                        tracing::warn!(index = index, err = %e, "delete status report failed");
+                    }
+                }
+            }
+        }
+    }
+
+    /// 发送队列中的一条短信（PDU 模式），从上次失败的分段继续
+    async fn send_job(&self, cfg: &Config, job: Job) {
+        let (alphabet, parts) =
+            match pdu::encode_submit(&job.number, &job.text, job.reference, cfg.delivery_reports) {
+                Ok(encoded) => encoded,
+                Err(e) => {
+                    self.queue
+                        .finish(cfg, &job, Vec::new(), Err(SendError::Invalid(e.to_string())));
+                    return;
+                }
+            };
+        tracing::info!(
+            id = job.id,
+            number = %job.number,
+            alphabet = ?alphabet,
+            segments = parts.len(),
+            attempt = job.attempts + 1,
+            "sending SMS via AT (PDU)"
+        );
+        let submitted = self.modem.send(parts, job.message_refs.len()).await;
+        let mut message_refs = job.message_refs.clone();
+        message_refs.extend(submitted.message_refs);
+        self.queue.finish(cfg, &job, message_refs, submitted.result);
+    }
+
+    /// 状态报告：更新发信记录；整条短信有了最终结果时按配置推送通知
+    fn status_report(&self, report: pdu::StatusReport) {
+        tracing::info!(
+            number = %report.recipient,
+            message_ref = report.message_ref,
+            status = report.status,
+            reason = pdu::status_reason(report.status),
+            "收到短信状态报告"
+        );
+        let update = match self.history.record_report(
+            &report.recipient,
+            report.message_ref,
+            report.status,
+        ) {
+            Ok(Some(update)) => update,
+            Ok(None) => {
+                tracing::warn!(
+                    number = %report.recipient,
+                    message_ref = report.message_ref,
+                    "status report matches no sent SMS"
+                );
+                return;
             }
+            Err(e) => {
+                tracing::error!(err = %e, "record status report failed");
+                return;
+            }
+        };
+        if !update.changed || update.status == DeliveryStatus::Sent {
+            return;
+        }
+        tracing::info!(
+            id = update.outbound_id,
+            number = %update.number,
+            segment = update.seq,
+            segments = update.segments,
+            status = update.status.as_str(),
+            "sent SMS status updated"
+        );
+        let cfg = self.cfg.get();
+        let notifiers = notify::enabled_names(&cfg);
+        if !cfg.delivery_report_notify || notifiers.is_empty() {
+            return;
         }
+        let (title, level) = match update.status {
+            DeliveryStatus::Delivered => (format!("短信已送达 {}", update.number), Level::Passive),
+            DeliveryStatus::Expired => (format!("短信过期未送达 {}", update.number), Level::Active),
+            _ => (format!("短信发送失败 {}", update.number), Level::Active),
+        };
+        let notification = Notification {
+            title,
+            body: format!("{}\n\n({})", update.text, pdu::status_reason(report.status)),
+            emergency: false,
+            level,
+            sender: update.number,
+            timestamp: report.discharged,
+            indices: Vec::new(),
+            bark: BarkOptions::default(),
+            history_id: None,
+        };
+        if let Err(e) = self.outbox.enqueue(&notifiers, notification, Delivery::Now) {
+            tracing::error!(err = %e, "enqueue status report notification failed");
+        }
+    }
 
-        drop(port);
-        sleep(Duration::from_secs(5)).await;
+    /// 兜底轮询：列出所有短信逐条转发，避免遗漏未上报的短信
+    async fn sweep(&mut self) -> AtResult<()> {
+        for ent in self.modem.list().await? {
+            tracing::info!(index = ?ent.index, number = %ent.number, "轮询到短信");
+            self.dispatch(ent).await;
+        }
+        Ok(())
+    }
+
+    /// 普通短信直接转发；长短信分段先进入重组缓冲，收齐后再整条转发
+    async fn dispatch(&mut self, ent: SmsEntry) {
+        let msg = match ent.concat {
+            Some(c) => {
+                tracing::debug!(
+                    number = %ent.number,
+                    reference = c.reference,
+                    seq = c.seq,
+                    total = c.total,
+                    "got SMS segment"
+                );
+                match self
+                    .reassembler
+                    .push(&ent.number, c, ent.index, ent.body, ent.raw, ent.timestamp)
+                {
+                    Some(msg) => msg,
+                    None => return,
+                }
+            }
+            None => Assembled {
+                number: ent.number,
+                body: ent.body,
+                indices: ent.index.into_iter().collect(),
+                timestamp: ent.timestamp,
+                complete: true,
+                raw: vec![ent.raw],
+            },
+        };
+        self.forward_and_delete(msg).await;
+    }
+
+    /// 通知写入发件箱后才从 SIM 删除；写入失败则保留在 SIM 上，由下一次兜底轮询重试
+    async fn forward_and_delete(&self, msg: Assembled) {
+        if !msg.complete {
+            tracing::warn!(number = %msg.number, "forwarding incomplete concatenated SMS");
+        }
+        if let Err(e) = handle_sms(&self.cfg.get(), &self.outbox, &self.history, &msg) {
+            if msg.indices.is_empty() {
+                tracing::error!(number = %msg.number, err = %e, "forward failed, SMS was not stored on SIM and is lost");
+            } else {
+                tracing::warn!(
+                    number = %msg.number,
+                    indices = ?msg.indices,
+                    err = %e,
+                    "forward failed, keeping SMS on SIM for retry"
+                );
+            }
+            return;
+        }
+        for index in msg.indices {
+            match self.modem.delete(index).await {
+                Ok(_) => tracing::debug!(index = index, "deleted"),
+                Err(e) => tracing::warn!(index = index, err = %e, "delete SMS failed"),
+            }
+        }
     }
 }
 
 fn decode_ucs2_hex(text: &str) -> Option<String> {
     let s = text.trim();
-    if s.is_empty() || s.len() % 4 != 0 || !s.chars().all(|c| c.is_ascii_hexdigit()) {
+    if s.is_empty() || !s.len().is_multiple_of(4) || !s.chars().all(|c| c.is_ascii_hexdigit()) {
         return None;
     }
 
@@ -216,7 +1194,7 @@ fn decode_ucs2_hex(text: &str) -> Option<String> {
         let b = u8::from_str_radix(&s[i..i + 2], 16).ok()?;
         bytes.push(b);
     }
-    if bytes.len() % 2 != 0 {
+    if !bytes.len().is_multiple_of(2) {
         return None;
     }
 
@@ -228,20 +1206,239 @@ fn decode_ucs2_hex(text: &str) -> Option<String> {
     String::from_utf16(&words).ok()
 }
 
-async fn handle_sms(cfg: &Config, number: String, text: String) {
-    let decoded = decode_ucs2_hex(&text).unwrap_or_else(|| text.clone());
+/// 推送级别、是否为紧急通知与投递时间。规则设置的级别优先于紧急关键字，
+/// 但命中紧急关键字或级别为 critical 的短信都不受免打扰时段影响
+fn schedule(
+    cfg: &Config,
+    number: &str,
+    body: &str,
+    rule_level: Option<Level>,
+    now: NaiveDateTime,
+) -> (Level, bool, Delivery) {
+    let keyword = cfg.emergency_keywords.iter().any(|kw| body.contains(kw));
+    let mut level = rule_level.unwrap_or(if keyword {
+        Level::Critical
+    } else {
+        Level::Active
+    });
+    let emergency = level == Level::Critical;
+    let mut delivery = Delivery::Now;
+    if !(keyword || emergency) {
+        if let Some((quiet, until)) = quiet::active(&cfg.quiet_hours, now) {
+            tracing::info!(number = %number, quiet = %quiet.name, action = ?quiet.action, "免打扰时段内");
+            let until = cfg.zone().to_utc(until);
+            match quiet.action {
+                QuietAction::Demote => level = Level::Passive,
+                QuietAction::Delay => delivery = Delivery::At(until),
+                QuietAction::Digest => delivery = Delivery::Digest(until),
+            }
+        }
+    }
+    (level, emergency, delivery)
+}
 
-    let emergency = cfg
-        .emergency_keywords
-        .iter()
-        .any(|kw| decoded.contains(kw));
-    let title = format!("SMS from {}", number);
+/// 生成通知并为每个已启用渠道写入发件箱；返回 Err 表示未能落盘，调用方不应删除该短信
+fn handle_sms(
+    cfg: &Config,
+    outbox: &Outbox,
+    history: &History,
+    msg: &Assembled,
+) -> anyhow::Result<()> {
+    let number = &msg.number;
+    let zone = cfg.zone();
+    let now = zone.now();
+    // 规则已在加载配置时校验，编译失败只可能是配置被绕过校验，按无规则处理
+    let decision = rules::Engine::new(&cfg.rules)
+        .map(|engine| {
+            engine.evaluate(&rules::Incoming {
+                sender: number,
+                body: &msg.body,
+                time: now.time(),
+                modem: &cfg.modem_device,
+            })
+        })
+        .unwrap_or_default();
+    // 历史记录写入失败不影响转发
+    let record = || {
+        history
+            .record_inbound(&history::Inbound {
+                sender: number,
+                timestamp: msg.timestamp,
+                body: &msg.body,
+                raw: &msg.raw,
+                complete: msg.complete,
+            })
+            .map_err(|e| tracing::error!(err = %e, "record SMS history failed"))
+            .ok()
+    };
+    if decision.drop {
+        tracing::info!(number = %number, rules = ?decision.matched, "短信被规则丢弃");
+        if let (Some(id), Some(rule)) = (record(), decision.matched.last()) {
+            if let Err(e) = history.mark_dropped(id, rule) {
+                tracing::error!(err = %e, "record SMS history failed");
+            }
+        }
+        return Ok(());
+    }
+    if !decision.matched.is_empty() {
+        tracing::debug!(number = %number, rules = ?decision.matched, "routing rules matched");
+    }
+
+    let enabled = notify::enabled_names(cfg);
+    let notifiers: Vec<String> = match &decision.notifiers {
+        Some(chosen) => enabled.into_iter().filter(|n| chosen.contains(n)).collect(),
+        None => enabled,
+    };
+    // 没有可用渠道时照常记入历史并从 SIM 删除，否则每次兜底轮询都会重新读到这条短信
+    if notifiers.is_empty() {
+        tracing::warn!(number = %number, rules = ?decision.matched, "没有已启用的通知渠道，短信只记入历史");
+        record();
+        return Ok(());
+    }
+    let (level, emergency, delivery) = schedule(cfg, number, &msg.body, decision.level, now);
+    let mut title = format!("SMS from {}", number);
+    let mut bark = BarkOptions::default();
 
     if emergency {
-        tracing::info!(number = %number, "emergency keyword matched, push as critical");
+        tracing::info!(number = %number, "emergency keyword or rule matched, push as critical");
+    }
+    // 正则已在加载配置时校验，这里编译失败只可能是配置被绕过校验，按未识别处理
+    let code = otp::Extractor::new(&cfg.otp_patterns)
+        .ok()
+        .and_then(|ex| ex.extract(&msg.body));
+    if let Some(code) = code {
+        tracing::info!(number = %number, "识别到验证码");
+        title = format!("{} · {}", code, title);
+        bark.copy = Some(code);
+        bark.auto_copy = Some(true);
+    }
+    // 规则中显式设置的 Bark 参数优先于验证码的自动复制
+    let bark = bark.merge(&decision.bark);
+    // 先记录以便通知带上记录 ID；写入发件箱失败时短信留在 SIM 上，下次处理时沿用这条记录
+    let history_id = record();
+    outbox.enqueue(
+        &notifiers,
+        Notification {
+            title,
+            body: msg.body.clone(),
+            emergency,
+            level,
+            sender: number.clone(),
+            timestamp: msg.timestamp,
+            indices: msg.indices.clone(),
+            bark,
+            history_id,
+        },
+        delivery,
+    )?;
+    tracing::info!(number = %number, notifiers = ?notifiers, "已加入发件箱");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(json: &str) -> Config {
+        serde_json::from_str(json).unwrap()
+    }
+
+    fn at(s: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M").unwrap()
     }
-    match bark::push(cfg, &title, &decoded, emergency).await {
-        Ok(()) => tracing::info!(number = %number, "转发成功"),
-        Err(e) => tracing::error!(number = %number, err = %e, "Bark push failed"),
+
+    const QUIET: &str = r#"{
+        "emergency_keywords": ["违规停车"],
+        "quiet_hours": [{"from": "22:00", "to": "07:00", "action": "delay"}],
+        "rules": [{"name": "all", "match": {}, "action": {"level": "active"}}]
+    }"#;
+
+    fn plan(cfg: &Config, body: &str, time: &str) -> (Level, bool, Delivery) {
+        let now = at(time);
+        let decision = rules::Engine::new(&cfg.rules).unwrap().evaluate(&rules::Incoming {
+            sender: "10086",
+            body,
+            time: now.time(),
+            modem: &cfg.modem_device,
+        });
+        schedule(cfg, "10086", body, decision.level, now)
+    }
+
+    #[test]
+    fn sms_without_enabled_notifier_is_recorded_and_released() {
+        let cfg = config(
+            r#"{
+                "emergency_keywords": [],
+                "notifiers": [
+                    {"name": "tg", "type": "telegram", "enabled": false, "bot_token": "t", "chat_id": "1"}
+                ]
+            }"#,
+        );
+        let path = std::env::temp_dir().join(format!("outbox-test-{}-modem.json", std::process::id()));
+        let path = path.to_string_lossy().into_owned();
+        let _ = std::fs::remove_file(&path);
+        let history = History::in_memory();
+        let outbox = Outbox::load_at(&path, history.clone());
+        let msg = Assembled {
+            number: "10086".to_string(),
+            body: "hello".to_string(),
+            indices: vec![3],
+            timestamp: None,
+            complete: true,
+            raw: vec!["hello".to_string()],
+        };
+        // 返回 Ok 后调用方会把短信从 SIM 删除
+        handle_sms(&cfg, &outbox, &history, &msg).unwrap();
+        assert_eq!(outbox.stats().depth, 0);
+        let page = history.messages(&history::Filter::default(), 1, 10).unwrap();
+        assert_eq!(page.total, 1);
+        assert!(page.items[0].forwards.is_empty());
+    }
+
+    #[test]
+    fn keyword_bypasses_quiet_hours_despite_rule_level() {
+        let cfg = config(QUIET);
+        let (level, _, delivery) = plan(&cfg, "您的车辆违规停车", "2024-05-06 23:00");
+        assert_eq!(level, Level::Active);
+        assert!(matches!(delivery, Delivery::Now));
+
+        // 其他短信按免打扰时段推迟
+        let (_, emergency, delivery) = plan(&cfg, "hello", "2024-05-06 23:00");
+        assert!(!emergency);
+        assert!(matches!(delivery, Delivery::At(_)));
+    }
+
+    #[test]
+    fn keyword_without_rule_is_critical() {
+        let cfg = config(
+            r#"{
+                "emergency_keywords": ["违规停车"],
+                "quiet_hours": [{"from": "22:00", "to": "07:00"}]
+            }"#,
+        );
+        let (level, emergency, delivery) = plan(&cfg, "违规停车", "2024-05-06 23:00");
+        assert_eq!(level, Level::Critical);
+        assert!(emergency);
+        assert!(matches!(delivery, Delivery::Now));
+
+        // demote：普通短信降为 passive
+        let (level, _, delivery) = plan(&cfg, "hello", "2024-05-06 23:00");
+        assert_eq!(level, Level::Passive);
+        assert!(matches!(delivery, Delivery::Now));
+    }
+
+    #[test]
+    fn critical_rule_bypasses_quiet_hours() {
+        let cfg = config(
+            r#"{
+                "emergency_keywords": [],
+                "quiet_hours": [{"from": "22:00", "to": "07:00", "action": "digest"}],
+                "rules": [{"match": {"sender": {"exact": "10086"}}, "action": {"level": "critical"}}]
+            }"#,
+        );
+        let (level, emergency, delivery) = plan(&cfg, "hello", "2024-05-06 23:00");
+        assert_eq!(level, Level::Critical);
+        assert!(emergency);
+        assert!(matches!(delivery, Delivery::Now));
     }
 }
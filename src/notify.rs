@@ -0,0 +1,133 @@
+// 通知渠道抽象：每种推送后端实现 Notifier，发件箱按渠道名称查找实例并发送
+
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use chrono::{DateTime, FixedOffset};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+
+use crate::bark::Bark;
+use crate::config::{BarkOptions, Config, NotifierKind};
+use crate::smtp::Smtp;
+use crate::telegram::Telegram;
+use crate::webhook::Webhook;
+
+/// HTTP 请求的整体超时与连接超时，服务端无响应时不会一直占住发送任务
+const HTTP_TIMEOUT: Duration = Duration::from_secs(30);
+const HTTP_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// 各 HTTP 渠道共用的客户端，复用连接池
+pub fn http_client() -> &'static Client {
+    static CLIENT: OnceLock<Client> = OnceLock::new();
+    CLIENT.get_or_init(|| {
+        Client::builder()
+            .timeout(HTTP_TIMEOUT)
+            .connect_timeout(HTTP_CONNECT_TIMEOUT)
+            .build()
+            .expect("build HTTP client")
+    })
+}
+
+/// 推送级别，与 iOS 通知的中断级别对应（Bark 的 level 参数）
+#[derive(Clone, Copy, Default, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Level {
+    /// 仅加入通知列表，不亮屏不响铃
+    Passive,
+    #[default]
+    Active,
+    /// 可突破专注模式
+    TimeSensitive,
+    /// 重要警告，静音与勿扰下仍响铃
+    Critical,
+}
+
+/// 一条待推送的通知
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Notification {
+    pub title: String,
+    pub body: String,
+    /// 紧急通知，即 level 为 critical
+    pub emergency: bool,
+    #[serde(default)]
+    pub level: Level,
+    /// 短信发送方号码
+    #[serde(default)]
+    pub sender: String,
+    /// 短信的服务中心时间戳
+    #[serde(default)]
+    pub timestamp: Option<DateTime<FixedOffset>>,
+    /// 短信在 modem 上的存储索引（长短信为各分段索引）
+    #[serde(default)]
+    pub indices: Vec<u32>,
+    /// 针对本条通知的 Bark 参数，覆盖渠道的 bark_options（由路由规则设置）
+    #[serde(default)]
+    pub bark: BarkOptions,
+    /// 对应的短信历史记录 ID，用于回写各渠道的转发状态
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub history_id: Option<i64>,
+}
+
+#[async_trait]
+pub trait Notifier: Send + Sync {
+    async fn send(&self, n: &Notification) -> anyhow::Result<()>;
+
+    /// 渠道有多个投递目标（如 Bark 的多台设备）时逐个发送：跳过 delivered 中已成功的目标，
+    /// 本次成功的目标加入 delivered，发件箱重试时只发给失败的目标；默认整体发送
+    async fn send_remaining(&self, n: &Notification, delivered: &mut Vec<String>) -> anyhow::Result<()> {
+        let _ = delivered;
+        self.send(n).await
+    }
+}
+
+/// 按配置创建渠道实例
+pub fn build(kind: &NotifierKind) -> Box<dyn Notifier> {
+    match kind {
+        NotifierKind::Bark(c) => Box::new(Bark::new(c.clone())),
+        NotifierKind::Telegram(c) => Box::new(Telegram::new(c.clone())),
+        NotifierKind::Webhook(c) => Box::new(Webhook::new(c.clone())),
+        NotifierKind::Smtp(c) => Box::new(Smtp::new(c.clone())),
+    }
+}
+
+/// 按名称查找已启用的渠道
+pub fn find(cfg: &Config, name: &str) -> Option<Box<dyn Notifier>> {
+    cfg.notifiers()
+        .into_iter()
+        .find(|n| n.enabled && n.name == name)
+        .map(|n| build(&n.kind))
+}
+
+/// 所有已启用渠道的名称
+pub fn enabled_names(cfg: &Config) -> Vec<String> {
+    cfg.notifiers()
+        .into_iter()
+        .filter(|n| n.enabled)
+        .map(|n| n.name)
+        .collect()
+}
+
+/// 测试用 HTTP 服务：对每个请求都返回给定的状态行与正文，返回服务地址
+#[cfg(test)]
+pub async fn http_stub(status: &'static str, body: &'static str) -> String {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            let mut buf = [0u8; 8192];
+            let _ = stream.read(&mut buf).await;
+            let response = format!(
+                "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                status,
+                body.len(),
+                body
+            );
+            let _ = stream.write_all(response.as_bytes()).await;
+        }
+    });
+    format!("http://{}", addr)
+}
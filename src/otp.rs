@@ -0,0 +1,117 @@
+// 验证码（OTP）识别：按配置的正则依次匹配短信正文，取第一个命中的验证码
+// 正则中名为 code 的捕获组优先，否则取第 1 个捕获组，没有捕获组时取整个匹配
+
+use regex::Regex;
+
+/// 默认识别规则，覆盖常见的中英文验证码短信格式
+pub fn default_patterns() -> Vec<String> {
+    [
+        // 验证码：123456 / 验证码为 123456 / 动态验证码123456
+        r"(?:验证码|校验码|动态码|确认码|动态密码|安全码)(?:是|为|:|：|\s|\(|（|\)|）)*(?P<code>\d{4,8})(?:\D|$)",
+        // 123456是您的验证码 / 123456（登录验证码）
+        r"(?:^|\D)(?P<code>\d{4,8})(?:\s|是|为|\(|（)*(?:您|你)?的?[^\d，。,.]{0,10}?(?:验证码|校验码|动态码|动态密码)",
+        // Your verification code is 123456 / code: 1234 / OTP 123456
+        r"(?i)\b(?:code|otp|passcode|pin)(?:\s+is)?[\s:：]*(?P<code>\d{4,8})(?:\D|$)",
+        // G-123456 is your Google verification code / 1234 is your OTP
+        r"(?i)(?:^|\D)(?P<code>\d{4,8}) is your[\w\s]{0,30}?(?:code|otp|pin|password)",
+    ]
+    .iter()
+    .map(|s| s.to_string())
+    .collect()
+}
+
+/// 编译后的识别规则
+pub struct Extractor {
+    patterns: Vec<Regex>,
+}
+
+impl Extractor {
+    /// 编译全部正则，任一非法即返回错误（错误信息含出错的正则）
+    pub fn new(patterns: &[String]) -> anyhow::Result<Self> {
+        let patterns = patterns
+            .iter()
+            .map(|p| Regex::new(p).map_err(|e| anyhow::anyhow!("验证码正则 {:?} 无效: {}", p, e)))
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Self { patterns })
+    }
+
+    /// 返回正文中的验证码，未识别时为 None
+    pub fn extract(&self, body: &str) -> Option<String> {
+        self.patterns.iter().find_map(|re| {
+            let caps = re.captures(body)?;
+            let m = caps
+                .name("code")
+                .or_else(|| caps.get(1))
+                .or_else(|| caps.get(0))?;
+            Some(m.as_str().to_string())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn extract(body: &str) -> Option<String> {
+        Extractor::new(&default_patterns()).unwrap().extract(body)
+    }
+
+    #[test]
+    fn chinese_formats() {
+        let corpus = [
+            ("【淘宝】验证码：386512，您正在登录，若非本人操作，请勿泄露。", "386512"),
+            ("【支付宝】验证码 2468，用于支付宝账户登录，5分钟内有效。", "2468"),
+            ("【京东】您的验证码为 901234，请勿泄露给他人。", "901234"),
+            ("【微信】您的动态验证码是：551200，15分钟内有效。", "551200"),
+            ("【中国银行】您正在进行网上支付，校验码：77316540，请勿告知他人。", "77316540"),
+            ("【滴滴出行】334455是您的登录验证码，请在10分钟内完成验证。", "334455"),
+            ("【12306】验证码:1357，您正在使用手机号登录中国铁路12306。", "1357"),
+            ("【美团】778899（登录验证码），请于5分钟内填写。", "778899"),
+            ("【工商银行】尾号1234的卡正在进行快捷支付，动态密码为 620913。", "620913"),
+        ];
+        for (body, code) in corpus {
+            assert_eq!(extract(body).as_deref(), Some(code), "{}", body);
+        }
+    }
+
+    #[test]
+    fn english_formats() {
+        let corpus = [
+            ("G-482913 is your Google verification code.", "482913"),
+            ("Your Uber code: 5821. Never share this code.", "5821"),
+            ("Your verification code is 731904. It expires in 10 minutes.", "731904"),
+            ("Telegram code: 40217", "40217"),
+            ("Your Amazon OTP is 118822. Do not share it with anyone.", "118822"),
+            ("1930 is your Twitter login code. Don't reply to this message.", "1930"),
+            ("Apple ID Code: 603218. Don't share it with anyone.", "603218"),
+        ];
+        for (body, code) in corpus {
+            assert_eq!(extract(body).as_deref(), Some(code), "{}", body);
+        }
+    }
+
+    #[test]
+    fn ignores_messages_without_code() {
+        let corpus = [
+            "【中国移动】您本月已使用流量 1024MB，剩余 2048MB。",
+            "【顺丰速运】您的快递已到达丰巢柜，取件码请在 App 中查看。",
+            "您尾号8888的储蓄卡消费1200.00元，余额35678.90元。",
+            "验证码5分钟内有效，请尽快完成操作。",
+            "Your package will arrive today between 2pm and 6pm.",
+        ];
+        for body in corpus {
+            assert_eq!(extract(body), None, "{}", body);
+        }
+    }
+
+    #[test]
+    fn custom_pattern_without_named_group() {
+        let ex = Extractor::new(&[r"口令\s*(\w{6})".to_string()]).unwrap();
+        assert_eq!(ex.extract("【某系统】口令 AB12CD，勿外传").as_deref(), Some("AB12CD"));
+    }
+
+    #[test]
+    fn rejects_invalid_pattern() {
+        assert!(Extractor::new(&["(unclosed".to_string()]).is_err());
+    }
+}
@@ -0,0 +1,633 @@
+// 通知发件箱：所有待推送的通知先写入 outbox.json，再由后台任务发送
+// 每个渠道各占一条，各渠道并行发送、互不影响；失败按指数退避重试，超过最长保留时间后丢弃；进程重启后从文件恢复
+// 免打扰时段推迟的通知在到期前不发送，标记为汇总的通知到期后按渠道合并为一条
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Notify;
+use tokio::task::JoinSet;
+use tokio::time::timeout;
+
+use crate::config::{BarkOptions, SharedConfig};
+use crate::history::{ForwardStatus, History};
+use crate::notify::{self, Level, Notification};
+
+const OUTBOX_FILE: &str = "outbox.json";
+/// 第一次重试的等待时间，之后每次翻倍
+const INITIAL_BACKOFF: Duration = Duration::from_secs(5);
+/// 单次发送的最长时间，超时按失败重试
+const SEND_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// 旧版发件箱条目没有渠道名称，均为默认 Bark
+fn default_notifier() -> String {
+    "bark".to_string()
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct Item {
+    id: u64,
+    /// 目标渠道名称
+    #[serde(default = "default_notifier")]
+    notifier: String,
+    notification: Notification,
+    created_at: DateTime<Utc>,
+    attempts: u32,
+    next_attempt_at: DateTime<Utc>,
+    last_error: Option<String>,
+    /// 到期后与同一渠道的其他汇总通知合并发送
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    digest: bool,
+    /// 渠道内已成功的投递目标（如 Bark 设备），重试时跳过
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    delivered: Vec<String>,
+}
+
+/// 通知的投递时间
+#[derive(Clone, Copy)]
+pub enum Delivery {
+    /// 立即发送
+    Now,
+    /// 推迟到指定时间后发送
+    At(DateTime<Utc>),
+    /// 推迟到指定时间后合并为汇总发送
+    Digest(DateTime<Utc>),
+}
+
+/// 队列状态，供 HTTP API 查看
+#[derive(Serialize)]
+pub struct Stats {
+    pub depth: usize,
+    pub oldest: Option<PendingInfo>,
+    /// 各渠道的待发送数与本次启动以来的成功/失败次数
+    pub notifiers: BTreeMap<String, NotifierStats>,
+}
+
+#[derive(Clone, Default, Serialize)]
+pub struct NotifierStats {
+    pub pending: usize,
+    pub delivered: u64,
+    pub failed: u64,
+    pub last_error: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct PendingInfo {
+    pub id: u64,
+    pub notifier: String,
+    pub title: String,
+    pub created_at: DateTime<Utc>,
+    pub attempts: u32,
+    pub next_attempt_at: DateTime<Utc>,
+    pub last_error: Option<String>,
+}
+
+#[derive(Clone)]
+pub struct Outbox {
+    /// 落盘文件路径
+    path: Arc<str>,
+    items: Arc<Mutex<Vec<Item>>>,
+    counters: Arc<Mutex<BTreeMap<String, NotifierStats>>>,
+    wake: Arc<Notify>,
+    history: History,
+}
+
+impl Outbox {
+    /// 从 outbox.json 恢复上次未发完的通知；文件不存在视为空队列
+    pub fn load(history: History) -> Self {
+        Self::load_at(OUTBOX_FILE, history)
+    }
+
+    /// 从指定文件恢复，供测试使用临时文件
+    pub fn load_at(path: &str, history: History) -> Self {
+        let items: Vec<Item> = match fs::read_to_string(path) {
+            Ok(txt) => serde_json::from_str(&txt).unwrap_or_else(|e| {
+                tracing::error!(err = %e, "outbox.json is corrupt, starting with an empty outbox");
+                Vec::new()
+            }),
+            Err(_) => Vec::new(),
+        };
+        if !items.is_empty() {
+            tracing::info!(pending = items.len(), "outbox restored");
+        }
+        Self {
+            path: path.into(),
+            items: Arc::new(Mutex::new(items)),
+            counters: Arc::new(Mutex::new(BTreeMap::new())),
+            wake: Arc::new(Notify::new()),
+            history,
+        }
+    }
+
+    /// 为每个渠道各写入一条并落盘；返回 Ok 后通知不会因进程退出而丢失
+    pub fn enqueue(
+        &self,
+        notifiers: &[String],
+        notification: Notification,
+        delivery: Delivery,
+    ) -> anyhow::Result<()> {
+        let now = Utc::now();
+        let (next_attempt_at, digest) = match delivery {
+            Delivery::Now => (now, false),
+            Delivery::At(t) => (t, false),
+            Delivery::Digest(t) => (t, true),
+        };
+        {
+            let mut items = self.items.lock().unwrap();
+            let before = items.len();
+            let first_id = items.iter().map(|i| i.id).max().unwrap_or(0) + 1;
+            for (id, notifier) in (first_id..).zip(notifiers) {
+                items.push(Item {
+                    id,
+                    notifier: notifier.clone(),
+                    notification: notification.clone(),
+                    created_at: now,
+                    attempts: 0,
+                    next_attempt_at,
+                    last_error: None,
+                    digest,
+                    delivered: Vec::new(),
+                });
+            }
+            if let Err(e) = self.persist(&items) {
+                items.truncate(before);
+                return Err(e);
+            }
+            for item in &items[before..] {
+                self.track(item, ForwardStatus::Pending);
+            }
+        }
+        self.wake.notify_one();
+        Ok(())
+    }
+
+    pub fn stats(&self) -> Stats {
+        let items = self.items.lock().unwrap();
+        let mut notifiers = self.counters.lock().unwrap().clone();
+        for i in items.iter() {
+            notifiers.entry(i.notifier.clone()).or_default().pending += 1;
+        }
+        Stats {
+            depth: items.len(),
+            notifiers,
+            oldest: items.iter().min_by_key(|i| i.created_at).map(|i| PendingInfo {
+                id: i.id,
+                notifier: i.notifier.clone(),
+                title: i.notification.title.clone(),
+                created_at: i.created_at,
+                attempts: i.attempts,
+                next_attempt_at: i.next_attempt_at,
+                last_error: i.last_error.clone(),
+            }),
+        }
+    }
+
+    /// 后台发送任务：各渠道各自发送到期的通知，同一渠道同时只发一条，某个渠道无响应不影响其他渠道；
+    /// 失败按指数退避重新排期；每条通知都按最新配置创建渠道
+    pub async fn run(self, shared: SharedConfig) {
+        // 正在发送的渠道；任务 ID -> 渠道名称
+        let mut sending = JoinSet::new();
+        let mut busy: HashMap<tokio::task::Id, String> = HashMap::new();
+        loop {
+            let cfg = shared.get();
+            let max_age = chrono::Duration::seconds(cfg.outbox_max_age_secs as i64);
+            let max_backoff = Duration::from_secs(cfg.outbox_max_backoff_secs.max(1));
+            let now = Utc::now();
+            let is_busy = |name: &str| busy.values().any(|n| n == name);
+            let (batches, next) = {
+                let mut items = self.items.lock().unwrap();
+                self.expire(&mut items, now, max_age, is_busy);
+                // 每个空闲渠道取最早到期的一条；汇总通知则取该渠道所有已到期、已送达目标相同的汇总条目一起发送
+                let mut batches: Vec<Vec<Item>> = Vec::new();
+                for first in items.iter().filter(|i| i.next_attempt_at <= now) {
+                    if is_busy(&first.notifier) || batches.iter().any(|b| b[0].notifier == first.notifier) {
+                        continue;
+                    }
+                    batches.push(if first.digest {
+                        items
+                            .iter()
+                            .filter(|i| {
+                                i.digest
+                                    && i.notifier == first.notifier
+                                    && i.next_attempt_at <= now
+                                    && i.delivered == first.delivered
+                            })
+                            .cloned()
+                            .collect()
+                    } else {
+                        vec![first.clone()]
+                    });
+                }
+                let next = items
+                    .iter()
+                    .filter(|i| i.next_attempt_at > now && !is_busy(&i.notifier))
+                    .map(|i| i.next_attempt_at)
+                    .min();
+                (batches, next)
+            };
+
+            let mut dropped = false;
+            for batch in batches {
+                let name = batch[0].notifier.clone();
+                let Some(notifier) = notify::find(&cfg, &name) else {
+                    let ids: Vec<u64> = batch.iter().map(|i| i.id).collect();
+                    tracing::warn!(ids = ?ids, notifier = %name, "notifier no longer configured, dropped");
+                    for i in &batch {
+                        self.track(i, ForwardStatus::Dropped);
+                    }
+                    let mut items = self.items.lock().unwrap();
+                    items.retain(|i| !ids.contains(&i.id));
+                    if let Err(e) = self.persist(&items) {
+                        tracing::error!(err = %e, "persist outbox failed");
+                    }
+                    dropped = true;
+                    continue;
+                };
+                let outbox = self.clone();
+                let handle = sending.spawn(async move {
+                    let notification = if batch.len() > 1 {
+                        digest(&batch)
+                    } else {
+                        batch[0].notification.clone()
+                    };
+                    // 超时时已成功的目标仍留在 delivered 中
+                    let mut delivered = batch[0].delivered.clone();
+                    let sent = notifier.send_remaining(&notification, &mut delivered);
+                    let result = match timeout(SEND_TIMEOUT, sent).await {
+                        Ok(result) => result,
+                        Err(_) => Err(anyhow::anyhow!("timed out after {}s", SEND_TIMEOUT.as_secs())),
+                    };
+                    outbox.finish(&batch, result, delivered, max_backoff);
+                });
+                busy.insert(handle.id(), name);
+            }
+            // 丢弃了已删除渠道的条目后重新检查，该渠道可能还有其他到期条目
+            if dropped {
+                continue;
+            }
+
+            let wait = next
+                .and_then(|t| (t - now).to_std().ok())
+                .unwrap_or(Duration::from_secs(3600));
+            tokio::select! {
+                Some(done) = sending.join_next_with_id() => {
+                    let id = match done {
+                        Ok((id, ())) => id,
+                        Err(e) => {
+                            tracing::error!(err = %e, "notification task failed");
+                            e.id()
+                        }
+                    };
+                    busy.remove(&id);
+                }
+                _ = tokio::time::sleep(wait) => {}
+                _ = self.wake.notified() => {}
+            }
+        }
+    }
+
+    /// 记录一次发送的结果：成功时移出发件箱，失败时记下已成功的目标并按退避重新排期
+    fn finish(
+        &self,
+        batch: &[Item],
+        result: anyhow::Result<()>,
+        delivered: Vec<String>,
+        max_backoff: Duration,
+    ) {
+        let item = &batch[0];
+        let ids: Vec<u64> = batch.iter().map(|i| i.id).collect();
+        self.record(&item.notifier, &result);
+        let mut items = self.items.lock().unwrap();
+        match result {
+            Ok(()) => {
+                tracing::info!(
+                    ids = ?ids,
+                    notifier = %item.notifier,
+                    attempts = item.attempts + 1,
+                    "notification delivered"
+                );
+                for i in batch {
+                    self.track(
+                        &Item {
+                            attempts: i.attempts + 1,
+                            ..i.clone()
+                        },
+                        ForwardStatus::Delivered,
+                    );
+                }
+                items.retain(|i| !ids.contains(&i.id));
+            }
+            Err(e) => {
+                for i in items.iter_mut().filter(|i| ids.contains(&i.id)) {
+                    i.attempts += 1;
+                    let backoff = retry_backoff(i.attempts, max_backoff);
+                    i.next_attempt_at = Utc::now() + backoff;
+                    i.last_error = Some(e.to_string());
+                    i.delivered = delivered.clone();
+                    tracing::warn!(
+                        id = i.id,
+                        notifier = %i.notifier,
+                        attempts = i.attempts,
+                        retry_in_secs = backoff.as_secs(),
+                        err = %e,
+                        "notification failed, will retry"
+                    );
+                    self.track(i, ForwardStatus::Pending);
+                }
+            }
+        }
+        if let Err(e) = self.persist(&items) {
+            tracing::error!(err = %e, "persist outbox failed");
+        }
+    }
+
+    /// 丢弃超过最长保留时间的条目；发送中的条目等结果出来再处理
+    fn expire(
+        &self,
+        items: &mut Vec<Item>,
+        now: DateTime<Utc>,
+        max_age: chrono::Duration,
+        is_busy: impl Fn(&str) -> bool,
+    ) {
+        let before = items.len();
+        items.retain(|i| {
+            let keep = is_busy(&i.notifier) || now - i.created_at < max_age;
+            if !keep {
+                tracing::error!(
+                    id = i.id,
+                    title = %i.notification.title,
+                    attempts = i.attempts,
+                    "notification expired, dropped"
+                );
+                self.track(i, ForwardStatus::Expired);
+            }
+            keep
+        });
+        if items.len() != before {
+            if let Err(e) = self.persist(items) {
+                tracing::error!(err = %e, "persist outbox failed");
+            }
+        }
+    }
+
+    /// 先写临时文件再改名，避免写到一半断电导致文件损坏
+    fn persist(&self, items: &[Item]) -> anyhow::Result<()> {
+        let tmp = format!("{}.tmp", self.path);
+        fs::write(&tmp, serde_json::to_string_pretty(items)?)?;
+        fs::rename(&tmp, &*self.path)?;
+        Ok(())
+    }
+
+    /// 把条目的转发状态写入短信历史
+    fn track(&self, item: &Item, status: ForwardStatus) {
+        let Some(id) = item.notification.history_id else {
+            return;
+        };
+        if let Err(e) = self.history.record_forward(
+            id,
+            &item.notifier,
+            status,
+            item.attempts,
+            item.last_error.as_deref(),
+        ) {
+            tracing::error!(err = %e, "record forward status failed");
+        }
+    }
+
+    fn record(&self, notifier: &str, result: &anyhow::Result<()>) {
+        let mut counters = self.counters.lock().unwrap();
+        let c = counters.entry(notifier.to_string()).or_default();
+        match result {
+            Ok(()) => c.delivered += 1,
+            Err(e) => {
+                c.failed += 1;
+                c.last_error = Some(e.to_string());
+            }
+        }
+    }
+}
+
+/// 第 attempts 次失败后的重试间隔：从 INITIAL_BACKOFF 起每次翻倍，不超过 max_backoff
+fn retry_backoff(attempts: u32, max_backoff: Duration) -> Duration {
+    INITIAL_BACKOFF
+        .saturating_mul(1 << attempts.saturating_sub(1).min(16))
+        .min(max_backoff)
+}
+
+/// 把多条推迟的通知合并为一条：每条短信一段，注明发送方与时间
+fn digest(batch: &[Item]) -> Notification {
+    let body = batch
+        .iter()
+        .map(|i| {
+            let n = &i.notification;
+            match n.timestamp {
+                Some(ts) => format!("{} {}\n{}", n.sender, ts.format("%m-%d %H:%M"), n.body),
+                None => format!("{}\n{}", n.sender, n.body),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n");
+    Notification {
+        title: format!("免打扰期间收到 {} 条短信", batch.len()),
+        body,
+        emergency: false,
+        level: Level::Active,
+        sender: String::new(),
+        timestamp: None,
+        indices: batch
+            .iter()
+            .flat_map(|i| i.notification.indices.iter().copied())
+            .collect(),
+        bark: BarkOptions::default(),
+        history_id: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::history::{Filter, Inbound};
+
+    /// 每个测试使用各自的文件，测试并行执行时互不影响
+    fn temp_file(name: &str) -> String {
+        let file = format!("outbox-test-{}-{}.json", std::process::id(), name);
+        let path = std::env::temp_dir().join(file);
+        let _ = fs::remove_file(&path);
+        path.to_string_lossy().into_owned()
+    }
+
+    fn notification(history_id: Option<i64>) -> Notification {
+        Notification {
+            title: "SMS from 10086".to_string(),
+            body: "hello".to_string(),
+            emergency: false,
+            level: Level::Active,
+            sender: "10086".to_string(),
+            timestamp: None,
+            indices: vec![1],
+            bark: BarkOptions::default(),
+            history_id,
+        }
+    }
+
+    fn inbound(history: &History) -> i64 {
+        history
+            .record_inbound(&Inbound {
+                sender: "10086",
+                timestamp: None,
+                body: "hello",
+                raw: &["hello".to_string()],
+                complete: true,
+            })
+            .unwrap()
+    }
+
+    /// 历史记录中各渠道的转发状态：(渠道, 状态, 尝试次数)
+    fn forwards(history: &History) -> Vec<(String, String, u32)> {
+        let page = history.messages(&Filter::default(), 1, 10).unwrap();
+        page.items[0]
+            .forwards
+            .iter()
+            .map(|f| (f.notifier.clone(), f.status.clone(), f.attempts))
+            .collect()
+    }
+
+    fn stats(outbox: &Outbox, name: &str) -> NotifierStats {
+        outbox.stats().notifiers.get(name).cloned().unwrap_or_default()
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_limit() {
+        let max = Duration::from_secs(600);
+        let schedule: Vec<u64> = (1..=9).map(|n| retry_backoff(n, max).as_secs()).collect();
+        assert_eq!(schedule, vec![5, 10, 20, 40, 80, 160, 320, 600, 600]);
+        assert_eq!(retry_backoff(u32::MAX, max), max);
+        assert_eq!(retry_backoff(3, Duration::from_secs(7)).as_secs(), 7);
+    }
+
+    #[test]
+    fn expired_items_are_dropped() {
+        let path = temp_file("expire");
+        let history = History::in_memory();
+        let id = inbound(&history);
+        let outbox = Outbox::load_at(&path, history.clone());
+        let names = ["a".to_string(), "b".to_string(), "c".to_string()];
+        outbox.enqueue(&names, notification(Some(id)), Delivery::Now).unwrap();
+
+        let now = Utc::now();
+        let mut items = outbox.items.lock().unwrap();
+        for i in items.iter_mut().filter(|i| i.notifier != "c") {
+            i.created_at = now - chrono::Duration::hours(2);
+        }
+        // b 正在发送，等发送结果出来再处理
+        outbox.expire(&mut items, now, chrono::Duration::hours(1), |n| n == "b");
+        let left: Vec<&str> = items.iter().map(|i| i.notifier.as_str()).collect();
+        assert_eq!(left, vec!["b", "c"]);
+        drop(items);
+
+        assert_eq!(Outbox::load_at(&path, history.clone()).items.lock().unwrap().len(), 2);
+        assert_eq!(
+            forwards(&history),
+            vec![
+                ("a".to_string(), "expired".to_string(), 0),
+                ("b".to_string(), "pending".to_string(), 0),
+                ("c".to_string(), "pending".to_string(), 0),
+            ]
+        );
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn reload_restores_pending_items() {
+        let path = temp_file("reload");
+        let history = History::in_memory();
+        let outbox = Outbox::load_at(&path, history.clone());
+        let at = Utc::now() + chrono::Duration::hours(8);
+        outbox.enqueue(&["a".to_string()], notification(None), Delivery::Digest(at)).unwrap();
+        outbox.enqueue(&["a".to_string(), "b".to_string()], notification(None), Delivery::Now).unwrap();
+        let second = outbox.items.lock().unwrap()[1].clone();
+        outbox.finish(
+            &[second],
+            Err(anyhow::anyhow!("HTTP status server error (500 Internal Server Error)")),
+            vec!["dev1".to_string()],
+            Duration::from_secs(600),
+        );
+
+        let reloaded = Outbox::load_at(&path, history.clone());
+        let items = reloaded.items.lock().unwrap();
+        let summary: Vec<(u64, &str, bool, u32)> = items
+            .iter()
+            .map(|i| (i.id, i.notifier.as_str(), i.digest, i.attempts))
+            .collect();
+        assert_eq!(summary, vec![(1, "a", true, 0), (2, "a", false, 1), (3, "b", false, 0)]);
+        assert_eq!(items[0].next_attempt_at, at);
+        assert_eq!(items[1].delivered, vec!["dev1".to_string()]);
+        assert!(items[1].last_error.as_deref().unwrap().contains("500"));
+        drop(items);
+
+        // 新条目的 ID 接着已有的编号
+        reloaded.enqueue(&["b".to_string()], notification(None), Delivery::Now).unwrap();
+        assert_eq!(reloaded.items.lock().unwrap()[3].id, 4);
+
+        fs::write(&path, "not json").unwrap();
+        assert!(Outbox::load_at(&path, history.clone()).items.lock().unwrap().is_empty());
+        let _ = fs::remove_file(&path);
+        assert!(Outbox::load_at(&path, history).items.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn failing_notifier_does_not_hold_up_others() {
+        let ok = notify::http_stub("200 OK", "{}").await;
+        let bad = notify::http_stub("500 Internal Server Error", "").await;
+        let cfg: Config = serde_json::from_value(serde_json::json!({
+            "emergency_keywords": [],
+            "notifiers": [
+                {"name": "ok", "type": "webhook", "url": ok},
+                {"name": "bad", "type": "webhook", "url": bad},
+            ],
+        }))
+        .unwrap();
+
+        let path = temp_file("run");
+        let history = History::in_memory();
+        let id = inbound(&history);
+        let outbox = Outbox::load_at(&path, history.clone());
+        tokio::spawn(outbox.clone().run(SharedConfig::new(cfg)));
+        outbox
+            .enqueue(&["bad".to_string(), "ok".to_string()], notification(Some(id)), Delivery::Now)
+            .unwrap();
+
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(10);
+        while stats(&outbox, "ok").delivered == 0 || stats(&outbox, "bad").failed == 0 {
+            assert!(tokio::time::Instant::now() < deadline, "notifications were not sent");
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+
+        assert_eq!(stats(&outbox, "ok").delivered, 1);
+        assert_eq!(stats(&outbox, "bad").delivered, 0);
+        {
+            let items = outbox.items.lock().unwrap();
+            assert_eq!(items.len(), 1);
+            assert_eq!(items[0].notifier, "bad");
+            assert_eq!(items[0].attempts, 1);
+            assert!(items[0].last_error.as_deref().unwrap().contains("500"));
+            // 第一次重试在 INITIAL_BACKOFF 之后
+            assert!(items[0].next_attempt_at > Utc::now() + chrono::Duration::seconds(3));
+        }
+        assert_eq!(
+            forwards(&history),
+            vec![
+                ("bad".to_string(), "pending".to_string(), 1),
+                ("ok".to_string(), "delivered".to_string(), 1),
+            ]
+        );
+        let reloaded = Outbox::load_at(&path, history);
+        assert_eq!(reloaded.items.lock().unwrap()[0].notifier, "bad");
+        let _ = fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,882 @@
+// SMS PDU 编解码（3GPP TS 23.040 / 23.038），用于 AT+CMGF=0 模式
+// 解码收到的 SMS-DELIVER：SMSC 地址、发送方地址、DCS、SCTS 时间戳、UDH 与 GSM-7/8-bit/UCS2 正文
+// 编码发出的 SMS-SUBMIT：能用 GSM-7 表示时用 GSM-7，否则用 UCS2，超长时按 8 位参考号拆成多段
+// 解码 SMS-STATUS-REPORT：按消息参考号对应到发出的短信，TP-ST 给出送达结果
+
+use anyhow::{bail, Context};
+use chrono::{DateTime, FixedOffset, NaiveDate};
+
+/// GSM 7-bit 默认字母表
+#[rustfmt::skip]
+const GSM7_BASIC: [char; 128] = [
+    '@', '£', '$', '¥', 'è', 'é', 'ù', 'ì', 'ò', 'Ç', '\n', 'Ø', 'ø', '\r', 'Å', 'å',
+    'Δ', '_', 'Φ', 'Γ', 'Λ', 'Ω', 'Π', 'Ψ', 'Σ', 'Θ', 'Ξ', '\u{1b}', 'Æ', 'æ', 'ß', 'É',
+    ' ', '!', '"', '#', '¤', '%', '&', '\'', '(', ')', '*', '+', ',', '-', '.', '/',
+    '0', '1', '2', '3', '4', '5', '6', '7', '8', '9', ':', ';', '<', '=', '>', '?',
+    '¡', 'A', 'B', 'C', 'D', 'E', 'F', 'G', 'H', 'I', 'J', 'K', 'L', 'M', 'N', 'O',
+    'P', 'Q', 'R', 'S', 'T', 'U', 'V', 'W', 'X', 'Y', 'Z', 'Ä', 'Ö', 'Ñ', 'Ü', '§',
+    '¿', 'a', 'b', 'c', 'd', 'e', 'f', 'g', 'h', 'i', 'j', 'k', 'l', 'm', 'n', 'o',
+    'p', 'q', 'r', 's', 't', 'u', 'v', 'w', 'x', 'y', 'z', 'ä', 'ö', 'ñ', 'ü', 'à',
+];
+
+/// GSM 7-bit 扩展表（前缀 ESC 0x1B）
+const GSM7_EXTENSION: [(u8, char); 10] = [
+    (0x0A, '\u{0c}'),
+    (0x14, '^'),
+    (0x28, '{'),
+    (0x29, '}'),
+    (0x2F, '\\'),
+    (0x3C, '['),
+    (0x3D, '~'),
+    (0x3E, ']'),
+    (0x40, '|'),
+    (0x65, '€'),
+];
+
+/// 用户数据编码方式（由 DCS 决定）
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Alphabet {
+    Gsm7,
+    Eight,
+    Ucs2,
+}
+
+/// UDH 中的一个信息单元（IEI + 数据）
+#[derive(Clone, Debug)]
+pub struct InformationElement {
+    pub id: u8,
+    pub data: Vec<u8>,
+}
+
+/// 长短信分段信息（UDH IEI 0x00 为 8 位参考号，0x08 为 16 位参考号）
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Concat {
+    pub reference: u16,
+    pub total: u8,
+    /// 从 1 开始的分段序号
+    pub seq: u8,
+}
+
+/// 解码后的 SMS-DELIVER
+#[derive(Debug)]
+pub struct Deliver {
+    /// 短信中心号码（PDU 未携带时为 None）
+    pub smsc: Option<String>,
+    /// 发送方号码；国际号码带 `+`，字母数字地址解码为文本
+    pub sender: String,
+    pub dcs: u8,
+    /// 服务中心时间戳（SCTS），带时区
+    pub timestamp: Option<DateTime<FixedOffset>>,
+    pub udh: Vec<InformationElement>,
+    pub text: String,
+}
+
+impl Deliver {
+    /// 从 UDH 中取出长短信分段信息；分段数或序号非法时忽略
+    pub fn concat(&self) -> Option<Concat> {
+        self.udh.iter().find_map(|ie| {
+            let (reference, total, seq) = match (ie.id, ie.data.as_slice()) {
+                (0x00, &[r, t, s]) => (r as u16, t, s),
+                (0x08, &[r1, r2, t, s]) => (u16::from_be_bytes([r1, r2]), t, s),
+                _ => return None,
+            };
+            (total > 1 && seq >= 1 && seq <= total).then_some(Concat {
+                reference,
+                total,
+                seq,
+            })
+        })
+    }
+}
+
+/// 解码后的 SMS-STATUS-REPORT
+#[derive(Debug)]
+pub struct StatusReport {
+    /// 原短信的 TP-MR，即 +CMGS 返回的消息参考号
+    pub message_ref: u8,
+    /// 原短信的收件人号码
+    pub recipient: String,
+    /// 送达或最终失败的时间（TP-DT）
+    pub discharged: Option<DateTime<FixedOffset>>,
+    /// TP-ST 状态码
+    pub status: u8,
+}
+
+/// TP-ST 状态说明（TS 23.040 9.2.3.15）
+pub fn status_reason(st: u8) -> &'static str {
+    match st {
+        0x00 => "received by the SME",
+        0x01 => "forwarded to the SME, delivery unconfirmed",
+        0x02 => "replaced by the SC",
+        0x20 | 0x60 => "congestion",
+        0x21 | 0x61 => "SME busy",
+        0x22 | 0x62 => "no response from SME",
+        0x23 | 0x63 => "service rejected",
+        0x24 | 0x44 | 0x64 => "quality of service not available",
+        0x25 | 0x65 => "error in SME",
+        0x40 => "remote procedure error",
+        0x41 => "incompatible destination",
+        0x42 => "connection rejected by SME",
+        0x43 => "not obtainable",
+        0x45 => "no interworking available",
+        0x46 => "SM validity period expired",
+        0x47 => "SM deleted by originating SME",
+        0x48 => "SM deleted by SC administration",
+        0x49 => "SM does not exist",
+        0x03..=0x1F => "delivered",
+        0x26..=0x3F => "temporary error, SC still trying",
+        0x66..=0x7F => "temporary error, SC stopped trying",
+        _ => "permanent error",
+    }
+}
+
+/// 按字节读取 PDU 的游标
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn byte(&mut self) -> anyhow::Result<u8> {
+        let b = *self.bytes.get(self.pos).context("PDU truncated")?;
+        self.pos += 1;
+        Ok(b)
+    }
+
+    fn take(&mut self, n: usize) -> anyhow::Result<&'a [u8]> {
+        let end = self.pos + n;
+        if end > self.bytes.len() {
+            bail!("PDU truncated");
+        }
+        let out = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(out)
+    }
+
+    fn rest(&self) -> &'a [u8] {
+        &self.bytes[self.pos..]
+    }
+}
+
+/// 十六进制字符串转字节；按字节处理，串口噪声带来的非 ASCII 字符返回错误而不是 panic
+pub fn hex_to_bytes(hex: &str) -> anyhow::Result<Vec<u8>> {
+    let hex = hex.trim().as_bytes();
+    if !hex.len().is_multiple_of(2) {
+        bail!("odd hex length");
+    }
+    let nibble = |c: u8| (c as char).to_digit(16).context("invalid hex");
+    hex.chunks_exact(2)
+        .map(|pair| Ok(((nibble(pair[0])? << 4) | nibble(pair[1])?) as u8))
+        .collect()
+}
+
+/// 半八位组（BCD，低位在前）转数字串，F 为填充
+fn decode_semi_octets(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len() * 2);
+    for b in bytes {
+        for nibble in [b & 0x0F, b >> 4] {
+            match nibble {
+                0..=9 => out.push((b'0' + nibble) as char),
+                0x0A => out.push('*'),
+                0x0B => out.push('#'),
+                0x0C..=0x0E => out.push((b'a' + nibble - 0x0C) as char),
+                _ => {}
+            }
+        }
+    }
+    out
+}
+
+/// 把打包的 7-bit 数据拆成 septet；skip 为开头跳过的 septet 数（UDH 及填充位）
+fn unpack_septets(bytes: &[u8], skip: usize, count: usize) -> Vec<u8> {
+    (skip..count)
+        .map(|i| {
+            let bit = i * 7;
+            let (idx, shift) = (bit / 8, bit % 8);
+            let lo = bytes.get(idx).copied().unwrap_or(0) as u16;
+            let hi = bytes.get(idx + 1).copied().unwrap_or(0) as u16;
+            ((((hi << 8) | lo) >> shift) & 0x7F) as u8
+        })
+        .collect()
+}
+
+/// GSM-7 septet 转文本，处理扩展表转义
+fn gsm7_to_string(septets: &[u8]) -> String {
+    let mut out = String::with_capacity(septets.len());
+    let mut escape = false;
+    for &s in septets {
+        if escape {
+            escape = false;
+            match GSM7_EXTENSION.iter().find(|(code, _)| *code == s) {
+                Some((_, c)) => out.push(*c),
+                // 未定义的扩展字符按规范显示为基本表字符
+                None => out.push(GSM7_BASIC[s as usize]),
+            }
+        } else if s == 0x1B {
+            escape = true;
+        } else {
+            out.push(GSM7_BASIC[s as usize]);
+        }
+    }
+    out
+}
+
+/// UCS2（大端 UTF-16）转文本
+fn ucs2_to_string(bytes: &[u8]) -> String {
+    let words: Vec<u16> = bytes
+        .chunks_exact(2)
+        .map(|c| u16::from_be_bytes([c[0], c[1]]))
+        .collect();
+    String::from_utf16_lossy(&words)
+}
+
+/// 按 TS 23.038 第 4 节由 DCS 判断编码
+fn alphabet(dcs: u8) -> Alphabet {
+    let by_bits = |bits: u8| match bits & 0x03 {
+        1 => Alphabet::Eight,
+        2 => Alphabet::Ucs2,
+        _ => Alphabet::Gsm7,
+    };
+    match dcs >> 4 {
+        0x0..=0x7 => by_bits(dcs >> 2),
+        0xE => Alphabet::Ucs2,
+        0xF if dcs & 0x04 != 0 => Alphabet::Eight,
+        _ => Alphabet::Gsm7,
+    }
+}
+
+/// 解码地址字段（TP-OA），len 为有效数字个数
+fn decode_address(r: &mut Reader, digits: u8) -> anyhow::Result<String> {
+    let toa = r.byte()?;
+    let bytes = r.take(digits.div_ceil(2) as usize)?;
+    match (toa >> 4) & 0x07 {
+        // 字母数字地址：GSM-7 打包
+        0x5 => Ok(gsm7_to_string(&unpack_septets(
+            bytes,
+            0,
+            digits as usize * 4 / 7,
+        ))),
+        0x1 => Ok(format!("+{}", decode_semi_octets(bytes))),
+        _ => Ok(decode_semi_octets(bytes)),
+    }
+}
+
+/// 解码 SCTS：7 个半八位组，最后一个为以 15 分钟为单位的时区，bit3 为符号位
+fn decode_timestamp(b: &[u8]) -> Option<DateTime<FixedOffset>> {
+    let swap = |x: u8| (x & 0x0F) * 10 + (x >> 4);
+    let quarters = ((b[6] & 0x07) * 10 + (b[6] >> 4)) as i32;
+    let offset = if b[6] & 0x08 != 0 {
+        -quarters
+    } else {
+        quarters
+    } * 15
+        * 60;
+    NaiveDate::from_ymd_opt(
+        2000 + swap(b[0]) as i32,
+        swap(b[1]) as u32,
+        swap(b[2]) as u32,
+    )?
+    .and_hms_opt(swap(b[3]) as u32, swap(b[4]) as u32, swap(b[5]) as u32)?
+    .and_local_timezone(FixedOffset::east_opt(offset)?)
+    .single()
+}
+
+/// 解析 UDH 信息单元列表（不含 UDHL 字节）
+fn parse_udh(mut bytes: &[u8]) -> Vec<InformationElement> {
+    let mut ies = Vec::new();
+    while bytes.len() >= 2 {
+        let (id, len) = (bytes[0], bytes[1] as usize);
+        if bytes.len() < 2 + len {
+            break;
+        }
+        ies.push(InformationElement {
+            id,
+            data: bytes[2..2 + len].to_vec(),
+        });
+        bytes = &bytes[2 + len..];
+    }
+    ies
+}
+
+/// 解码 AT+CMGR / AT+CMGL / +CMT 给出的十六进制 PDU（含 SMSC 前缀）
+pub fn decode_deliver(hex: &str) -> anyhow::Result<Deliver> {
+    let bytes = hex_to_bytes(hex)?;
+    let mut r = Reader {
+        bytes: &bytes,
+        pos: 0,
+    };
+
+    let smsc_len = r.byte()? as usize;
+    let smsc = if smsc_len > 0 {
+        let toa = r.byte()?;
+        let digits = decode_semi_octets(r.take(smsc_len - 1)?);
+        Some(if (toa >> 4) & 0x07 == 0x1 {
+            format!("+{}", digits)
+        } else {
+            digits
+        })
+    } else {
+        None
+    };
+
+    let first = r.byte()?;
+    if first & 0x03 != 0 {
+        bail!("not an SMS-DELIVER PDU (MTI {})", first & 0x03);
+    }
+    let udhi = first & 0x40 != 0;
+
+    let oa_len = r.byte()?;
+    let sender = decode_address(&mut r, oa_len)?;
+    let _pid = r.byte()?;
+    let dcs = r.byte()?;
+    let timestamp = decode_timestamp(r.take(7)?);
+    let udl = r.byte()? as usize;
+    let ud = r.rest();
+
+    let header_len = if udhi {
+        1 + *ud.first().context("UDH missing")? as usize
+    } else {
+        0
+    };
+    if header_len > ud.len() {
+        bail!("UDH longer than user data");
+    }
+    if udl < header_len {
+        bail!("UDL shorter than UDH");
+    }
+    let udh = if udhi {
+        parse_udh(&ud[1..header_len])
+    } else {
+        Vec::new()
+    };
+
+    let text = match alphabet(dcs) {
+        Alphabet::Gsm7 => {
+            let skip = (header_len * 8).div_ceil(7);
+            gsm7_to_string(&unpack_septets(ud, skip, udl))
+        }
+        Alphabet::Ucs2 => ucs2_to_string(&ud[header_len..udl.min(ud.len())]),
+        Alphabet::Eight => {
+            let data = &ud[header_len..udl.min(ud.len())];
+            String::from_utf8(data.to_vec())
+                .unwrap_or_else(|_| data.iter().map(|b| format!("{:02X}", b)).collect())
+        }
+    };
+
+    Ok(Deliver {
+        smsc,
+        sender,
+        dcs,
+        timestamp,
+        udh,
+        text,
+    })
+}
+
+/// 解码 +CDS 或 AT+CMGR 给出的 SMS-STATUS-REPORT（含 SMSC 前缀）
+pub fn decode_status_report(hex: &str) -> anyhow::Result<StatusReport> {
+    let bytes = hex_to_bytes(hex)?;
+    let mut r = Reader {
+        bytes: &bytes,
+        pos: 0,
+    };
+    let smsc_len = r.byte()? as usize;
+    r.take(smsc_len)?;
+
+    let first = r.byte()?;
+    if first & 0x03 != 0x02 {
+        bail!("not an SMS-STATUS-REPORT PDU (MTI {})", first & 0x03);
+    }
+    let message_ref = r.byte()?;
+    let ra_len = r.byte()?;
+    let recipient = decode_address(&mut r, ra_len)?;
+    let _scts = r.take(7)?;
+    let discharged = decode_timestamp(r.take(7)?);
+    let status = r.byte()?;
+    Ok(StatusReport {
+        message_ref,
+        recipient,
+        discharged,
+        status,
+    })
+}
+
+/// 单段短信容量：GSM-7 为 160 个 septet，UCS2 为 70 个 UTF-16 码元
+const GSM7_SINGLE: usize = 160;
+const UCS2_SINGLE: usize = 70;
+/// 长短信每段容量（扣除 6 字节拼接 UDH）
+const GSM7_PART: usize = 153;
+const UCS2_PART: usize = 67;
+/// TP-VP 相对有效期：0xAA 为 4 天
+const VALIDITY_4_DAYS: u8 = 0xAA;
+
+/// 一段待发送的 SMS-SUBMIT
+#[derive(Debug)]
+pub struct Submit {
+    /// 十六进制 PDU，SMSC 长度为 00，即使用 SIM 中设置的短信中心
+    pub hex: String,
+    /// TPDU 字节数（不含 SMSC 部分），即 AT+CMGS=<length> 的参数
+    pub length: usize,
+}
+
+/// 字符对应的 GSM-7 septet（扩展表字符为 ESC + 码值）；无法表示时返回 None
+fn gsm7_septets(c: char) -> Option<Vec<u8>> {
+    // 0x1B 是转义符本身，不作为字符发送
+    if c == '\u{1b}' {
+        return None;
+    }
+    if let Some(pos) = GSM7_BASIC.iter().position(|&b| b == c) {
+        return Some(vec![pos as u8]);
+    }
+    GSM7_EXTENSION
+        .iter()
+        .find(|(_, e)| *e == c)
+        .map(|(code, _)| vec![0x1B, *code])
+}
+
+/// 把 septet 打包为 7-bit 数据；fill 为开头的填充位数（UDH 之后对齐到 septet 边界）
+fn pack_septets(septets: &[u8], fill: usize) -> Vec<u8> {
+    let bits = fill + septets.len() * 7;
+    let mut out = vec![0u8; bits.div_ceil(8)];
+    for (i, &s) in septets.iter().enumerate() {
+        let bit = fill + i * 7;
+        let (idx, shift) = (bit / 8, bit % 8);
+        let v = ((s & 0x7F) as u16) << shift;
+        out[idx] |= v as u8;
+        if v > 0xFF {
+            out[idx + 1] |= (v >> 8) as u8;
+        }
+    }
+    out
+}
+
+/// TP-DA 最多容纳的号码位数
+pub const MAX_ADDRESS_DIGITS: usize = 20;
+
+/// 编码目标地址（TP-DA）：长度为数字个数，`+` 开头为国际号码
+fn encode_address(number: &str) -> anyhow::Result<Vec<u8>> {
+    let (toa, digits) = match number.strip_prefix('+') {
+        Some(rest) => (0x91, rest),
+        None => (0x81, number),
+    };
+    if digits.is_empty() || digits.len() > MAX_ADDRESS_DIGITS || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        bail!("invalid destination number {:?}", number);
+    }
+    let mut out = vec![digits.len() as u8, toa];
+    for pair in digits.as_bytes().chunks(2) {
+        let lo = pair[0] - b'0';
+        let hi = pair.get(1).map_or(0x0F, |d| d - b'0');
+        out.push((hi << 4) | lo);
+    }
+    Ok(out)
+}
+
+/// 把各字符的编码单元按容量分段，不拆开同一字符的单元（GSM-7 转义、UTF-16 代理对）
+fn split_units(units: Vec<Vec<u8>>, single: usize, part: usize) -> Vec<Vec<u8>> {
+    let total: usize = units.iter().map(Vec::len).sum();
+    if total <= single {
+        return vec![units.concat()];
+    }
+    let mut parts = vec![Vec::new()];
+    for unit in units {
+        let current = parts.last_mut().unwrap();
+        if current.len() + unit.len() > part {
+            parts.push(unit);
+        } else {
+            current.extend(unit);
+        }
+    }
+    parts
+}
+
+/// 编码 SMS-SUBMIT；正文超过单段容量时拆成多段，reference 为各段 UDH 中的拼接参考号
+/// status_report 为 true 时请求状态报告（TP-SRR）；返回选用的编码与按顺序发送的分段
+pub fn encode_submit(
+    number: &str,
+    text: &str,
+    reference: u8,
+    status_report: bool,
+) -> anyhow::Result<(Alphabet, Vec<Submit>)> {
+    if text.is_empty() {
+        bail!("empty message");
+    }
+    let address = encode_address(number)?;
+    let gsm7: Option<Vec<Vec<u8>>> = text.chars().map(gsm7_septets).collect();
+    // GSM-7 时每段为 septet，UCS2 时每段为 UTF-16 码元（每个码元 2 字节）
+    let (alphabet, parts) = match gsm7 {
+        Some(units) => (Alphabet::Gsm7, split_units(units, GSM7_SINGLE, GSM7_PART)),
+        None => {
+            let units = text
+                .chars()
+                .map(|c| {
+                    let mut buf = [0u16; 2];
+                    c.encode_utf16(&mut buf)
+                        .iter()
+                        .flat_map(|w| w.to_be_bytes())
+                        .collect::<Vec<u8>>()
+                })
+                .collect();
+            (
+                Alphabet::Ucs2,
+                split_units(units, UCS2_SINGLE * 2, UCS2_PART * 2),
+            )
+        }
+    };
+    if parts.len() > u8::MAX as usize {
+        bail!("message too long ({} segments)", parts.len());
+    }
+
+    let total = parts.len() as u8;
+    let concat = total > 1;
+    let submits = parts
+        .into_iter()
+        .enumerate()
+        .map(|(i, data)| {
+            // SMS-SUBMIT，TP-VPF 为相对有效期；请求状态报告时置 TP-SRR，多段时置 UDHI
+            let first = 0x01
+                | 0x10
+                | if status_report { 0x20 } else { 0x00 }
+                | if concat { 0x40 } else { 0x00 };
+            let mut tpdu = vec![first, 0x00];
+            tpdu.extend_from_slice(&address);
+            tpdu.push(0x00); // TP-PID
+            tpdu.push(match alphabet {
+                Alphabet::Ucs2 => 0x08,
+                _ => 0x00,
+            });
+            tpdu.push(VALIDITY_4_DAYS);
+            let udh: &[u8] = if concat {
+                &[0x05, 0x00, 0x03, reference, total, i as u8 + 1]
+            } else {
+                &[]
+            };
+            match alphabet {
+                Alphabet::Ucs2 => {
+                    tpdu.push((udh.len() + data.len()) as u8);
+                    tpdu.extend_from_slice(udh);
+                    tpdu.extend_from_slice(&data);
+                }
+                _ => {
+                    // UDH 之后补齐到 septet 边界，TP-UDL 按 septet 计（含 UDH 占用的 septet）
+                    let header_septets = (udh.len() * 8).div_ceil(7);
+                    let fill = header_septets * 7 - udh.len() * 8;
+                    tpdu.push((header_septets + data.len()) as u8);
+                    tpdu.extend_from_slice(udh);
+                    tpdu.extend(pack_septets(&data, fill));
+                }
+            }
+            let mut hex = String::with_capacity(2 + tpdu.len() * 2);
+            hex.push_str("00");
+            for b in &tpdu {
+                hex.push_str(&format!("{:02X}", b));
+            }
+            Submit {
+                hex,
+                length: tpdu.len(),
+            }
+        })
+        .collect();
+    Ok((alphabet, submits))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 拼出一条 SMS-DELIVER：无 SMSC，发送方为 +8613800138000，SCTS 为 2024-05-06 12:34:56 +08:00
+    fn deliver(first: u8, dcs: u8, udl: usize, ud: &[u8]) -> String {
+        let mut pdu = vec![0x00, first, 0x0D, 0x91, 0x68, 0x31, 0x08, 0x10, 0x83, 0x00, 0xF0, 0x00, dcs];
+        pdu.extend_from_slice(&[0x42, 0x50, 0x60, 0x21, 0x43, 0x65, 0x23]);
+        pdu.push(udl as u8);
+        pdu.extend_from_slice(ud);
+        pdu.iter().map(|b| format!("{:02X}", b)).collect()
+    }
+
+    fn septets(text: &str) -> Vec<u8> {
+        text.chars().flat_map(|c| gsm7_septets(c).unwrap()).collect()
+    }
+
+    #[test]
+    fn classic_deliver() {
+        let d = decode_deliver("07911326040000F0040B911346610089F60000208062917314080CC8F71D14969741F977FD07")
+            .unwrap();
+        assert_eq!(d.smsc.as_deref(), Some("+31624000000"));
+        assert_eq!(d.sender, "+31641600986");
+        assert_eq!(d.dcs, 0x00);
+        assert_eq!(d.text, "How are you?");
+        assert_eq!(
+            d.timestamp.unwrap().naive_local().to_string(),
+            "2002-08-26 19:37:41"
+        );
+        assert!(d.udh.is_empty());
+        assert_eq!(d.concat(), None);
+    }
+
+    #[test]
+    fn timestamp_and_national_sender() {
+        let mut hex = deliver(0x04, 0x00, 2, &pack_septets(&septets("hi"), 0));
+        // 国内号码（TOA 0x81）
+        hex.replace_range(6..8, "81");
+        let d = decode_deliver(&hex).unwrap();
+        assert_eq!(d.sender, "8613800138000");
+        assert_eq!(d.timestamp.unwrap().to_rfc3339(), "2024-05-06T12:34:56+08:00");
+        assert_eq!(d.text, "hi");
+    }
+
+    #[test]
+    fn concat_8bit_reference_with_fill_bits() {
+        // 6 字节 UDH 占 7 个 septet，正文前有 1 个填充位
+        let body = septets("Hello {world}");
+        let mut ud = vec![0x05, 0x00, 0x03, 0x2A, 0x02, 0x01];
+        ud.extend(pack_septets(&body, 1));
+        let d = decode_deliver(&deliver(0x44, 0x00, 7 + body.len(), &ud)).unwrap();
+        assert_eq!(d.text, "Hello {world}");
+        assert_eq!(
+            d.concat(),
+            Some(Concat {
+                reference: 0x2A,
+                total: 2,
+                seq: 1
+            })
+        );
+    }
+
+    #[test]
+    fn concat_16bit_reference_ucs2() {
+        // 7 字节 UDH（IEI 0x08），UCS2 正文
+        let mut ud = vec![0x06, 0x08, 0x04, 0x12, 0x34, 0x03, 0x03];
+        ud.extend("短信".encode_utf16().flat_map(|w| w.to_be_bytes()));
+        let d = decode_deliver(&deliver(0x44, 0x08, ud.len(), &ud)).unwrap();
+        assert_eq!(d.text, "短信");
+        assert_eq!(
+            d.concat(),
+            Some(Concat {
+                reference: 0x1234,
+                total: 3,
+                seq: 3
+            })
+        );
+    }
+
+    #[test]
+    fn concat_with_invalid_seq_is_ignored() {
+        let mut ud = vec![0x05, 0x00, 0x03, 0x01, 0x02, 0x03];
+        ud.extend_from_slice(&[0x00, 0x41]);
+        let d = decode_deliver(&deliver(0x44, 0x08, ud.len(), &ud)).unwrap();
+        assert_eq!(d.text, "A");
+        assert_eq!(d.concat(), None);
+    }
+
+    #[test]
+    fn alphanumeric_sender() {
+        // "Google"：6 个 septet 共 42 位，地址长度按半八位组计为 11
+        let name = pack_septets(&septets("Google"), 0);
+        let mut pdu = vec![0x00, 0x04, 0x0B, 0xD0];
+        pdu.extend_from_slice(&name);
+        pdu.extend_from_slice(&[0x00, 0x00]);
+        pdu.extend_from_slice(&[0x42, 0x50, 0x60, 0x21, 0x43, 0x65, 0x23]);
+        pdu.push(2);
+        pdu.extend(pack_septets(&septets("ok"), 0));
+        let hex: String = pdu.iter().map(|b| format!("{:02X}", b)).collect();
+        let d = decode_deliver(&hex).unwrap();
+        assert_eq!(d.sender, "Google");
+        assert_eq!(d.text, "ok");
+    }
+
+    #[test]
+    fn udl_shorter_than_udh_is_rejected() {
+        // UDHI 置位、DCS 0x04（8-bit）、UDL 为 0，但 UDH 长度字节占 1 字节
+        let hex = deliver(0x44, 0x04, 0, &[0x00]);
+        let err = decode_deliver(&hex).unwrap_err();
+        assert_eq!(err.to_string(), "UDL shorter than UDH");
+        let hex = deliver(0x44, 0x08, 3, &[0x05, 0x00, 0x03, 0x01, 0x02, 0x01]);
+        assert!(decode_deliver(&hex).is_err());
+    }
+
+    #[test]
+    fn truncated_pdu_is_rejected() {
+        assert!(decode_deliver("0004").is_err());
+        assert!(decode_deliver("00040D91683108").is_err());
+        assert!(decode_deliver("0").is_err());
+        // SMS-SUBMIT 不是 SMS-DELIVER
+        assert!(decode_deliver("0011000B916407281553F80000AA0AE8329BFD4697D9EC37").is_err());
+    }
+
+    #[test]
+    fn submit_reference_vector() {
+        let (alphabet, parts) = encode_submit("+46708251358", "hellohello", 0, false).unwrap();
+        assert_eq!(alphabet, Alphabet::Gsm7);
+        assert_eq!(parts.len(), 1);
+        assert_eq!(parts[0].hex, "0011000B916407281553F80000AA0AE8329BFD4697D9EC37");
+        assert_eq!(parts[0].length, 23);
+    }
+
+    #[test]
+    fn submit_requests_status_report() {
+        let (_, parts) = encode_submit("10086", "hi", 0, true).unwrap();
+        // TP-SRR 置位，国内号码 TOA 0x81，奇数位补 F
+        assert!(parts[0].hex.starts_with("00310005810180F6"));
+    }
+
+    /// 分段的 TP-UDL 与 UDH
+    fn udl_and_udh(part: &Submit) -> (u8, Vec<u8>) {
+        let bytes = hex_to_bytes(&part.hex).unwrap();
+        // SMSC 长度、首字节、MR、DA 长度
+        let da_digits = bytes[3] as usize;
+        let udl_at = 4 + 1 + da_digits.div_ceil(2) + 3;
+        let udh = if bytes[1] & 0x40 != 0 {
+            bytes[udl_at + 1..udl_at + 7].to_vec()
+        } else {
+            Vec::new()
+        };
+        (bytes[udl_at], udh)
+    }
+
+    #[test]
+    fn gsm7_segment_boundaries() {
+        let (_, parts) = encode_submit("10086", &"a".repeat(160), 1, false).unwrap();
+        assert_eq!(parts.len(), 1);
+        assert_eq!(udl_and_udh(&parts[0]), (160, Vec::new()));
+
+        let (_, parts) = encode_submit("10086", &"a".repeat(161), 9, false).unwrap();
+        assert_eq!(parts.len(), 2);
+        // 153 个 septet 加 UDH 占用的 7 个
+        assert_eq!(udl_and_udh(&parts[0]), (160, vec![0x05, 0x00, 0x03, 9, 2, 1]));
+        assert_eq!(udl_and_udh(&parts[1]), (7 + 8, vec![0x05, 0x00, 0x03, 9, 2, 2]));
+        assert_eq!(parts[0].length, hex_to_bytes(&parts[0].hex).unwrap().len() - 1);
+
+        let (_, parts) = encode_submit("10086", &"a".repeat(153 * 3), 9, false).unwrap();
+        assert_eq!(parts.len(), 3);
+    }
+
+    #[test]
+    fn ucs2_segment_boundaries() {
+        let (alphabet, parts) = encode_submit("10086", &"中".repeat(70), 1, false).unwrap();
+        assert_eq!(alphabet, Alphabet::Ucs2);
+        assert_eq!(parts.len(), 1);
+        assert_eq!(udl_and_udh(&parts[0]).0, 140);
+
+        let (_, parts) = encode_submit("10086", &"中".repeat(71), 1, false).unwrap();
+        assert_eq!(parts.len(), 2);
+        assert_eq!(udl_and_udh(&parts[0]).0, 6 + 134);
+        assert_eq!(udl_and_udh(&parts[1]).0, 6 + 8);
+    }
+
+    #[test]
+    fn extension_char_counts_twice_and_is_not_split() {
+        // 159 个字符，但 € 占 2 个 septet，共 160，仍为单段
+        let text = format!("{}€", "a".repeat(158));
+        let (_, parts) = encode_submit("10086", &text, 1, false).unwrap();
+        assert_eq!(parts.len(), 1);
+        assert_eq!(udl_and_udh(&parts[0]).0, 160);
+
+        // 第一段剩 1 个 septet 时 ESC 与码值一起放到下一段
+        let units: Vec<Vec<u8>> = format!("{}{{{}", "a".repeat(152), "a".repeat(10))
+            .chars()
+            .map(|c| gsm7_septets(c).unwrap())
+            .collect();
+        let parts = split_units(units, GSM7_SINGLE, GSM7_PART);
+        assert_eq!(parts.len(), 2);
+        assert_eq!(parts[0].len(), 152);
+        assert_eq!(&parts[1][..2], &[0x1B, 0x28]);
+    }
+
+    #[test]
+    fn surrogate_pair_is_not_split() {
+        let text = format!("{}😀{}", "中".repeat(66), "中".repeat(10));
+        let units: Vec<Vec<u8>> = text
+            .chars()
+            .map(|c| {
+                let mut buf = [0u16; 2];
+                c.encode_utf16(&mut buf).iter().flat_map(|w| w.to_be_bytes()).collect()
+            })
+            .collect();
+        let parts = split_units(units, UCS2_SINGLE * 2, UCS2_PART * 2);
+        assert_eq!(parts.len(), 2);
+        assert_eq!(parts[0].len(), 66 * 2);
+        assert_eq!(&parts[1][..4], &[0xD8, 0x3D, 0xDE, 0x00]);
+
+        let (alphabet, submits) = encode_submit("10086", &text, 1, false).unwrap();
+        assert_eq!(alphabet, Alphabet::Ucs2);
+        assert_eq!(udl_and_udh(&submits[0]).0, 6 + 132);
+    }
+
+    #[test]
+    fn address_limits() {
+        assert!(encode_submit(&"1".repeat(20), "hi", 0, false).is_ok());
+        assert!(encode_submit(&format!("+{}", "1".repeat(20)), "hi", 0, false).is_ok());
+        assert!(encode_submit(&"1".repeat(21), "hi", 0, false).is_err());
+        assert!(encode_submit("+", "hi", 0, false).is_err());
+        assert!(encode_submit("1008a", "hi", 0, false).is_err());
+        assert!(encode_submit("10086", "", 0, false).is_err());
+    }
+
+    #[test]
+    fn status_report() {
+        // 无 SMSC，MR 0x29，收件人 +8613800138000，TP-DT 2026-10-12 15:00:00 +00:00，TP-ST 0
+        let hex = "0006290D91683108108300F062012151000000620121510000000000";
+        let r = decode_status_report(hex).unwrap();
+        assert_eq!(r.message_ref, 0x29);
+        assert_eq!(r.recipient, "+8613800138000");
+        assert_eq!(r.status, 0x00);
+        assert_eq!(r.discharged.unwrap().to_rfc3339(), "2026-10-12T15:00:00+00:00");
+        assert_eq!(status_reason(r.status), "received by the SME");
+    }
+
+    #[test]
+    fn status_report_with_smsc_and_failure() {
+        let hex = "0891683108200105F006070D91683108108300F0620121510000006201215100000046";
+        let r = decode_status_report(hex).unwrap();
+        assert_eq!(r.message_ref, 0x07);
+        assert_eq!(r.status, 0x46);
+        assert_eq!(status_reason(r.status), "SM validity period expired");
+    }
+
+    #[test]
+    fn status_report_rejects_other_pdus() {
+        assert!(decode_status_report("07911326040000F0040B911346610089F60000208062917314080CC8F71D14969741F977FD07").is_err());
+        // 截断在 TP-ST 之前
+        assert!(decode_status_report("0006290D91683108108300F06201215100000062012151000000").is_err());
+    }
+
+    #[test]
+    fn garbled_hex_is_an_error() {
+        // 串口噪声经 from_utf8_lossy 后会带上 U+FFFD 等多字节字符
+        for hex in ["00\u{FFFD}06", "0\u{FFFD}", "é0", "0006290", "0G", "\u{FFFD}"] {
+            assert!(hex_to_bytes(hex).is_err(), "{:?}", hex);
+            assert!(decode_deliver(hex).is_err(), "{:?}", hex);
+            assert!(decode_status_report(hex).is_err(), "{:?}", hex);
+        }
+        assert_eq!(hex_to_bytes(" 0aFF\r").unwrap(), vec![0x0A, 0xFF]);
+    }
+
+    #[test]
+    fn malformed_pdus_are_errors_not_panics() {
+        let deliver = "07911326040000F0040B911346610089F60000208062917314080CC8F71D14969741F977FD07";
+        let report = "0891683108200105F006070D91683108108300F0620121510000006201215100000046";
+        assert!(decode_deliver(deliver).is_ok());
+        assert!(decode_status_report(report).is_ok());
+        // 在用户数据之前的任意位置截断都应返回错误
+        for end in (0..deliver.len() - 26).step_by(2) {
+            assert!(decode_deliver(&deliver[..end]).is_err(), "{}", end);
+        }
+        for end in (0..report.len()).step_by(2) {
+            assert!(decode_status_report(&report[..end]).is_err(), "{}", end);
+        }
+        // 伪随机字节，覆盖各种长度字段与 UDH 组合
+        let mut seed = 0x2545F491u32;
+        for _ in 0..2000 {
+            let len = (seed % 64) as usize;
+            let hex: String = (0..len)
+                .map(|_| {
+                    seed ^= seed << 13;
+                    seed ^= seed >> 17;
+                    seed ^= seed << 5;
+                    format!("{:02X}", seed as u8)
+                })
+                .collect();
+            let _ = decode_deliver(&hex);
+            let _ = decode_status_report(&hex);
+        }
+    }
+}
@@ -0,0 +1,159 @@
+// 免打扰时段：时段内的非紧急通知降级为静默、推迟到时段结束，或在结束时合并为一条汇总
+// 时间按配置的时区计算，未配置时使用系统本地时区
+
+use chrono::{DateTime, Datelike, Local, NaiveDateTime, TimeZone, Utc, Weekday};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+
+use crate::rules::TimeRange;
+
+/// 时段内通知的处理方式
+#[derive(Clone, Copy, Default, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QuietAction {
+    /// 照常推送，但级别降为 passive（不亮屏不响铃）
+    #[default]
+    Demote,
+    /// 推迟到时段结束后逐条推送
+    Delay,
+    /// 时段结束后合并为一条汇总推送
+    Digest,
+}
+
+/// 一个免打扰时段
+#[derive(Clone, Serialize, Deserialize)]
+pub struct QuietHours {
+    /// 时段名称，仅用于日志
+    #[serde(default)]
+    pub name: String,
+    #[serde(flatten)]
+    pub window: TimeRange,
+    /// 生效的星期（以时段开始那天计），为空时每天生效
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub days: Vec<Weekday>,
+    #[serde(default)]
+    pub action: QuietAction,
+}
+
+/// 计算免打扰时段所用的时区
+#[derive(Clone, Copy)]
+pub enum Zone {
+    Local,
+    Named(Tz),
+}
+
+impl Zone {
+    /// 当前时区下的本地时间
+    pub fn now(self) -> NaiveDateTime {
+        match self {
+            Zone::Local => Local::now().naive_local(),
+            Zone::Named(tz) => Utc::now().with_timezone(&tz).naive_local(),
+        }
+    }
+
+    /// 本地时间转为 UTC；夏令时切换造成的重复时间取较早者，不存在的时间顺延一小时
+    pub fn to_utc(self, local: NaiveDateTime) -> DateTime<Utc> {
+        fn convert<Z: TimeZone>(zone: &Z, local: NaiveDateTime) -> DateTime<Utc> {
+            zone.from_local_datetime(&local)
+                .earliest()
+                .or_else(|| {
+                    zone.from_local_datetime(&(local + chrono::Duration::hours(1)))
+                        .earliest()
+                })
+                .map(|t| t.with_timezone(&Utc))
+                .unwrap_or_else(|| local.and_utc())
+        }
+        match self {
+            Zone::Local => convert(&Local, local),
+            Zone::Named(tz) => convert(&tz, local),
+        }
+    }
+}
+
+/// 当前所处的免打扰时段：返回第一个命中时段的处理方式及其结束的本地时间
+pub fn active(windows: &[QuietHours], now: NaiveDateTime) -> Option<(&QuietHours, NaiveDateTime)> {
+    let t = now.time();
+    windows.iter().find_map(|w| {
+        if !w.window.contains(t) {
+            return None;
+        }
+        let overnight = w.window.from > w.window.to;
+        // 跨午夜时段的后半段属于前一天开始的时段
+        let start_day = if overnight && t < w.window.to {
+            now.date().pred_opt()?
+        } else {
+            now.date()
+        };
+        if !w.days.is_empty() && !w.days.contains(&start_day.weekday()) {
+            return None;
+        }
+        let end_day = if overnight {
+            start_day.succ_opt()?
+        } else {
+            start_day
+        };
+        Some((w, end_day.and_time(w.window.to)))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn windows(json: &str) -> Vec<QuietHours> {
+        serde_json::from_str(json).unwrap()
+    }
+
+    fn at(s: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    #[test]
+    fn overnight_window_ends_next_morning() {
+        let w = windows(r#"[{"from": "23:00", "to": "07:30", "action": "digest"}]"#);
+        let (hit, end) = active(&w, at("2024-05-06 23:15")).unwrap();
+        assert_eq!(hit.action, QuietAction::Digest);
+        assert_eq!(end, at("2024-05-07 07:30"));
+
+        let (_, end) = active(&w, at("2024-05-07 03:00")).unwrap();
+        assert_eq!(end, at("2024-05-07 07:30"));
+
+        assert!(active(&w, at("2024-05-07 07:30")).is_none());
+        assert!(active(&w, at("2024-05-07 12:00")).is_none());
+    }
+
+    #[test]
+    fn days_refer_to_the_day_the_window_starts() {
+        // 2024-05-10 为周五
+        let w = windows(r#"[{"from": "22:00", "to": "08:00", "days": ["Fri", "Sat"]}]"#);
+        assert!(active(&w, at("2024-05-10 23:00")).is_some());
+        assert!(active(&w, at("2024-05-11 02:00")).is_some());
+        assert!(active(&w, at("2024-05-12 23:00")).is_none());
+        // 周一凌晨属于周日开始的时段
+        assert!(active(&w, at("2024-05-13 02:00")).is_none());
+    }
+
+    #[test]
+    fn first_matching_window_wins() {
+        let w = windows(
+            r#"[
+                {"name": "lunch", "from": "12:00", "to": "13:00", "action": "delay"},
+                {"name": "day", "from": "09:00", "to": "18:00"}
+            ]"#,
+        );
+        let (hit, end) = active(&w, at("2024-05-06 12:30")).unwrap();
+        assert_eq!(hit.name, "lunch");
+        assert_eq!(end, at("2024-05-06 13:00"));
+        let (hit, _) = active(&w, at("2024-05-06 15:00")).unwrap();
+        assert_eq!(hit.action, QuietAction::Demote);
+    }
+
+    #[test]
+    fn named_zone_converts_to_utc() {
+        let zone = Zone::Named(chrono_tz::Asia::Shanghai);
+        assert_eq!(
+            zone.to_utc(at("2024-05-07 07:30")),
+            Utc.with_ymd_and_hms(2024, 5, 6, 23, 30, 0).unwrap()
+        );
+    }
+}
@@ -0,0 +1,312 @@
+// 短信路由规则：按顺序匹配发送方、正文、时段与 modem，命中的规则依次修改转发决定
+// 规则可指定通知渠道、推送级别、Bark 参数，丢弃短信或停止匹配后续规则
+
+use chrono::NaiveTime;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::config::BarkOptions;
+use crate::notify::Level;
+
+/// 发送方匹配方式，JSON 中写作 {"exact": ...}、{"prefix": ...} 或 {"regex": ...}
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SenderMatch {
+    Exact(String),
+    Prefix(String),
+    Regex(String),
+}
+
+/// 一天中的时段，to 早于 from 时表示跨越午夜
+#[derive(Clone, Serialize, Deserialize)]
+pub struct TimeRange {
+    pub from: NaiveTime,
+    pub to: NaiveTime,
+}
+
+impl TimeRange {
+    pub fn contains(&self, t: NaiveTime) -> bool {
+        if self.from <= self.to {
+            self.from <= t && t < self.to
+        } else {
+            t >= self.from || t < self.to
+        }
+    }
+}
+
+/// 匹配条件，未设置的条件视为满足
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Match {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sender: Option<SenderMatch>,
+    /// 正文正则
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub body: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub time: Option<TimeRange>,
+    /// 接收短信的 modem 串口设备路径
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub modem: Option<String>,
+}
+
+/// 命中后执行的动作
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Action {
+    /// 只推送到这些渠道（替换之前规则的选择）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub notifiers: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub level: Option<Level>,
+    /// 覆盖 Bark 渠道的附加参数
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bark: Option<BarkOptions>,
+    /// 丢弃短信，不再推送
+    pub drop: bool,
+    /// 不再匹配后续规则
+    pub stop: bool,
+}
+
+/// 一条路由规则
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Rule {
+    /// 规则名称，仅用于日志
+    pub name: String,
+    #[serde(rename = "match")]
+    pub matches: Match,
+    pub action: Action,
+}
+
+/// 参与匹配的短信信息
+pub struct Incoming<'a> {
+    pub sender: &'a str,
+    pub body: &'a str,
+    /// 收到短信时的本地时间
+    pub time: NaiveTime,
+    pub modem: &'a str,
+}
+
+/// 全部规则执行后的转发决定
+#[derive(Default)]
+pub struct Decision {
+    /// 为 None 时推送到全部已启用渠道
+    pub notifiers: Option<Vec<String>>,
+    pub level: Option<Level>,
+    pub bark: BarkOptions,
+    pub drop: bool,
+    /// 命中的规则名称
+    pub matched: Vec<String>,
+}
+
+/// 编译后的规则
+struct Compiled {
+    rule: Rule,
+    sender: Option<Regex>,
+    body: Option<Regex>,
+}
+
+impl Compiled {
+    fn matches(&self, msg: &Incoming) -> bool {
+        let m = &self.rule.matches;
+        let sender_ok = match &m.sender {
+            None => true,
+            Some(SenderMatch::Exact(s)) => msg.sender == s,
+            Some(SenderMatch::Prefix(p)) => msg.sender.starts_with(p.as_str()),
+            Some(SenderMatch::Regex(_)) => self
+                .sender
+                .as_ref()
+                .is_some_and(|re| re.is_match(msg.sender)),
+        };
+        sender_ok
+            && self.body.as_ref().is_none_or(|re| re.is_match(msg.body))
+            && m.time.as_ref().is_none_or(|t| t.contains(msg.time))
+            && m.modem.as_ref().is_none_or(|d| d == msg.modem)
+    }
+}
+
+pub struct Engine {
+    rules: Vec<Compiled>,
+}
+
+impl Engine {
+    /// 编译规则中的正则，出错时错误信息指明是第几条规则
+    pub fn new(rules: &[Rule]) -> anyhow::Result<Self> {
+        let compile = |i: usize, what: &str, p: &str| {
+            Regex::new(p).map_err(|e| {
+                anyhow::anyhow!("规则 #{} 的 {} 正则 {:?} 无效: {}", i + 1, what, p, e)
+            })
+        };
+        let mut compiled = Vec::with_capacity(rules.len());
+        for (i, rule) in rules.iter().enumerate() {
+            let sender = match &rule.matches.sender {
+                Some(SenderMatch::Regex(p)) => Some(compile(i, "sender", p)?),
+                _ => None,
+            };
+            let body = match &rule.matches.body {
+                Some(p) => Some(compile(i, "body", p)?),
+                None => None,
+            };
+            compiled.push(Compiled {
+                rule: rule.clone(),
+                sender,
+                body,
+            });
+        }
+        Ok(Self { rules: compiled })
+    }
+
+    /// 按顺序执行规则；遇到 drop 立即返回，遇到 stop 停止匹配
+    pub fn evaluate(&self, msg: &Incoming) -> Decision {
+        let mut decision = Decision::default();
+        for c in &self.rules {
+            if !c.matches(msg) {
+                continue;
+            }
+            let action = &c.rule.action;
+            decision.matched.push(c.rule.name.clone());
+            if action.drop {
+                decision.drop = true;
+                return decision;
+            }
+            if let Some(n) = &action.notifiers {
+                decision.notifiers = Some(n.clone());
+            }
+            if let Some(level) = action.level {
+                decision.level = Some(level);
+            }
+            if let Some(bark) = &action.bark {
+                decision.bark = decision.bark.merge(bark);
+            }
+            if action.stop {
+                break;
+            }
+        }
+        decision
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(json: &str) -> Engine {
+        Engine::new(&serde_json::from_str::<Vec<Rule>>(json).unwrap()).unwrap()
+    }
+
+    fn msg<'a>(sender: &'a str, body: &'a str, time: &str) -> Incoming<'a> {
+        Incoming {
+            sender,
+            body,
+            time: time.parse().unwrap(),
+            modem: "/dev/ttyUSB2",
+        }
+    }
+
+    #[test]
+    fn no_rules_keeps_defaults() {
+        let d = rules("[]").evaluate(&msg("10086", "hello", "12:00"));
+        assert!(!d.drop);
+        assert!(d.notifiers.is_none());
+        assert!(d.level.is_none());
+        assert!(d.matched.is_empty());
+    }
+
+    #[test]
+    fn sender_matchers() {
+        let engine = rules(
+            r#"[
+                {"name": "exact", "match": {"sender": {"exact": "10086"}}, "action": {"notifiers": ["a"]}},
+                {"name": "prefix", "match": {"sender": {"prefix": "955"}}, "action": {"notifiers": ["b"]}},
+                {"name": "regex", "match": {"sender": {"regex": "^\\+44"}}, "action": {"notifiers": ["c"]}}
+            ]"#,
+        );
+        let pick = |sender| engine.evaluate(&msg(sender, "x", "12:00")).notifiers;
+        assert_eq!(pick("10086"), Some(vec!["a".to_string()]));
+        assert_eq!(pick("100861"), None);
+        assert_eq!(pick("95588"), Some(vec!["b".to_string()]));
+        assert_eq!(pick("+447700900123"), Some(vec!["c".to_string()]));
+    }
+
+    #[test]
+    fn body_regex_and_level() {
+        let engine = rules(
+            r#"[{"name": "fraud", "match": {"body": "(?i)alarm|报警"}, "action": {"level": "timeSensitive"}}]"#,
+        );
+        assert!(matches!(
+            engine.evaluate(&msg("1", "触发报警", "12:00")).level,
+            Some(Level::TimeSensitive)
+        ));
+        assert!(engine.evaluate(&msg("1", "hello", "12:00")).level.is_none());
+    }
+
+    #[test]
+    fn time_range_across_midnight() {
+        let engine = rules(
+            r#"[{"name": "night", "match": {"time": {"from": "22:00", "to": "07:00"}}, "action": {"level": "passive"}}]"#,
+        );
+        let level = |t| engine.evaluate(&msg("1", "x", t)).level;
+        assert!(matches!(level("23:30"), Some(Level::Passive)));
+        assert!(matches!(level("06:59"), Some(Level::Passive)));
+        assert!(level("07:00").is_none());
+        assert!(level("12:00").is_none());
+    }
+
+    #[test]
+    fn modem_condition() {
+        let engine = rules(r#"[{"match": {"modem": "/dev/ttyUSB5"}, "action": {"drop": true}}]"#);
+        assert!(!engine.evaluate(&msg("1", "x", "12:00")).drop);
+    }
+
+    #[test]
+    fn later_rules_override_until_stop() {
+        let engine = rules(
+            r#"[
+                {"name": "all", "action": {"notifiers": ["bark"], "level": "active"}},
+                {"name": "bank", "match": {"sender": {"prefix": "955"}}, "action": {"notifiers": ["mail"], "stop": true}},
+                {"name": "never", "action": {"level": "critical"}}
+            ]"#,
+        );
+        let d = engine.evaluate(&msg("95588", "x", "12:00"));
+        assert_eq!(d.notifiers, Some(vec!["mail".to_string()]));
+        assert!(matches!(d.level, Some(Level::Active)));
+        assert_eq!(d.matched, vec!["all", "bank"]);
+
+        let d = engine.evaluate(&msg("10010", "x", "12:00"));
+        assert!(matches!(d.level, Some(Level::Critical)));
+    }
+
+    #[test]
+    fn drop_short_circuits() {
+        let engine = rules(
+            r#"[
+                {"name": "spam", "match": {"body": "退订"}, "action": {"drop": true}},
+                {"name": "all", "action": {"notifiers": ["bark"]}}
+            ]"#,
+        );
+        let d = engine.evaluate(&msg("1069", "优惠活动，回T退订", "12:00"));
+        assert!(d.drop);
+        assert_eq!(d.matched, vec!["spam"]);
+    }
+
+    #[test]
+    fn bark_options_merge() {
+        let engine = rules(
+            r#"[
+                {"action": {"bark": {"group": "bank", "icon": "a.png"}}},
+                {"action": {"bark": {"icon": "b.png"}}}
+            ]"#,
+        );
+        let d = engine.evaluate(&msg("1", "x", "12:00"));
+        assert_eq!(d.bark.group.as_deref(), Some("bank"));
+        assert_eq!(d.bark.icon.as_deref(), Some("b.png"));
+    }
+
+    #[test]
+    fn invalid_regex_is_reported() {
+        let rules: Vec<Rule> = serde_json::from_str(r#"[{}, {"match": {"body": "("}}]"#).unwrap();
+        let err = Engine::new(&rules).err().unwrap().to_string();
+        assert!(err.contains("#2"), "{}", err);
+    }
+}
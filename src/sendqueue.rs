@@ -0,0 +1,361 @@
+// 发送队列：POST /send 提交的短信先写入 sendqueue.json，再由持有串口的收信会话逐条发送
+// 按配置限制每分钟发送条数；网络拥塞等临时错误按指数退避重试，长短信从失败的分段继续；进程重启后从文件恢复
+
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::sync::{oneshot, Notify};
+
+use crate::config::Config;
+use crate::history::History;
+use crate::modem::{SendError, Sent};
+use crate::pdu;
+
+const QUEUE_FILE: &str = "sendqueue.json";
+/// 第一次重试的等待时间，之后每次翻倍
+const INITIAL_BACKOFF: Duration = Duration::from_secs(30);
+const MAX_BACKOFF: Duration = Duration::from_secs(600);
+/// 速率限制的统计窗口
+const RATE_WINDOW: chrono::Duration = chrono::Duration::seconds(60);
+
+/// 发送成功或最终失败的结果
+type Outcome = Result<Sent, SendError>;
+
+/// 队列中的一条短信
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Job {
+    pub id: u64,
+    /// 短信历史中的发信记录 ID
+    pub history_id: Option<i64>,
+    pub number: String,
+    pub text: String,
+    /// 长短信拼接参考号，重试时保持不变
+    pub reference: u8,
+    /// 已发出分段的消息参考号，重试时从下一段继续
+    pub message_refs: Vec<Option<u8>>,
+    pub created_at: DateTime<Utc>,
+    pub attempts: u32,
+    pub next_attempt_at: DateTime<Utc>,
+    pub last_error: Option<String>,
+}
+
+/// 提交成功后的凭据
+pub struct Ticket {
+    /// 短信历史中的发信记录 ID，记录失败时为 None
+    pub history_id: Option<i64>,
+    /// 发送成功或最终失败时收到结果
+    pub result: oneshot::Receiver<Outcome>,
+}
+
+/// 下一条可以发送的短信
+pub enum Due {
+    Job(Job),
+    /// 暂时没有可发送的短信（等待重试或受速率限制），到指定时间再检查
+    At(DateTime<Utc>),
+    /// 队列为空
+    Idle,
+}
+
+/// 队列状态，供 HTTP API 查看
+#[derive(Serialize)]
+pub struct Stats {
+    pub depth: usize,
+    /// 最近一分钟内发出的条数
+    pub sent_last_minute: usize,
+    pub jobs: Vec<Job>,
+}
+
+/// 一次开始发送的时间与号码
+type Attempt = (DateTime<Utc>, String);
+
+#[derive(Clone)]
+pub struct SendQueue {
+    jobs: Arc<Mutex<Vec<Job>>>,
+    /// 最近一分钟内开始发送的短信，用于速率限制
+    recent: Arc<Mutex<VecDeque<Attempt>>>,
+    /// 等待结果的提交方，按队列 ID 索引
+    waiters: Arc<Mutex<HashMap<u64, oneshot::Sender<Outcome>>>>,
+    wake: Arc<Notify>,
+    history: History,
+}
+
+/// 发送前检查号码与内容；不合法的请求不会进入队列，也不记入历史
+fn check(number: &str, text: &str) -> Result<(), SendError> {
+    let digits = number.strip_prefix('+').unwrap_or(number);
+    if digits.is_empty()
+        || digits.len() > pdu::MAX_ADDRESS_DIGITS
+        || !digits.chars().all(|c| c.is_ascii_digit())
+    {
+        return Err(SendError::Invalid(format!("invalid number {:?}", number)));
+    }
+    if text.is_empty() {
+        return Err(SendError::Invalid("text is empty".to_string()));
+    }
+    Ok(())
+}
+
+impl SendQueue {
+    /// 从 sendqueue.json 恢复上次未发出的短信；文件不存在视为空队列
+    pub fn load(history: History) -> Self {
+        let jobs: Vec<Job> = match fs::read_to_string(QUEUE_FILE) {
+            Ok(txt) => serde_json::from_str(&txt).unwrap_or_else(|e| {
+                tracing::error!(err = %e, "sendqueue.json is corrupt, starting with an empty queue");
+                Vec::new()
+            }),
+            Err(_) => Vec::new(),
+        };
+        if !jobs.is_empty() {
+            tracing::info!(pending = jobs.len(), "send queue restored");
+        }
+        Self {
+            jobs: Arc::new(Mutex::new(jobs)),
+            recent: Arc::new(Mutex::new(VecDeque::new())),
+            waiters: Arc::new(Mutex::new(HashMap::new())),
+            wake: Arc::new(Notify::new()),
+            history,
+        }
+    }
+
+    /// 检查后写入队列并落盘，记入短信历史（状态 queued）；返回 Ok 后短信不会因进程退出而丢失
+    pub fn submit(&self, number: &str, text: &str) -> Result<Ticket, SendError> {
+        check(number, text)?;
+        let history_id = self
+            .history
+            .queue_outbound(number, text)
+            .inspect_err(|e| tracing::error!(err = %e, "record outbound SMS failed"))
+            .ok();
+        let (tx, rx) = oneshot::channel();
+        let now = Utc::now();
+        let (id, depth) = {
+            let mut jobs = self.jobs.lock().unwrap();
+            let id = jobs.iter().map(|j| j.id).max().unwrap_or(0) + 1;
+            jobs.push(Job {
+                id,
+                history_id,
+                number: number.to_string(),
+                text: text.to_string(),
+                reference: rand::random(),
+                message_refs: Vec::new(),
+                created_at: now,
+                attempts: 0,
+                next_attempt_at: now,
+                last_error: None,
+            });
+            if let Err(e) = persist(&jobs) {
+                jobs.pop();
+                let err = SendError::Queue(format!("persist send queue failed: {}", e));
+                if let Some(hid) = history_id {
+                    if let Err(e) = self.history.complete_outbound(hid, Err(&err.to_string())) {
+                        tracing::error!(err = %e, "record outbound SMS failed");
+                    }
+                }
+                return Err(err);
+            }
+            self.waiters.lock().unwrap().insert(id, tx);
+            (id, jobs.len())
+        };
+        tracing::info!(id, number = %number, depth, "短信已加入发送队列");
+        self.wake.notify_one();
+        Ok(Ticket {
+            history_id,
+            result: rx,
+        })
+    }
+
+    /// 等待新提交的短信
+    pub async fn notified(&self) {
+        self.wake.notified().await
+    }
+
+    /// 取下一条可以发送的短信（按提交顺序，跳过未到重试时间或号码已达速率上限的），并计入速率统计；
+    /// 超过 send_max_age_secs 仍未发出的短信在这里放弃
+    pub fn next_due(&self, cfg: &Config) -> Due {
+        let now = Utc::now();
+        let max_age = chrono::Duration::seconds(cfg.send_max_age_secs as i64);
+        let expired: Vec<Job> = {
+            let mut jobs = self.jobs.lock().unwrap();
+            let (expired, keep) = jobs.drain(..).partition(|j| now - j.created_at >= max_age);
+            *jobs = keep;
+            if !expired.is_empty() {
+                if let Err(e) = persist(&jobs) {
+                    tracing::error!(err = %e, "persist send queue failed");
+                }
+            }
+            expired
+        };
+        for job in expired {
+            tracing::error!(
+                id = job.id,
+                number = %job.number,
+                attempts = job.attempts,
+                "send queue job expired, dropped"
+            );
+            let message_refs = job.message_refs.clone();
+            self.settle(&job, message_refs, Err(SendError::Expired));
+        }
+
+        let mut recent = self.recent.lock().unwrap();
+        while recent.front().is_some_and(|(t, _)| now - *t >= RATE_WINDOW) {
+            recent.pop_front();
+        }
+        let jobs = self.jobs.lock().unwrap();
+        if jobs.is_empty() {
+            return Due::Idle;
+        }
+        let limit = cfg.send_rate_limit as usize;
+        if limit > 0 && recent.len() >= limit {
+            // 等到窗口内的条数降到上限以下
+            return Due::At(recent[recent.len() - limit].0 + RATE_WINDOW);
+        }
+        let per_number = cfg.send_rate_limit_per_number as usize;
+        let mut wake_at: Option<DateTime<Utc>> = None;
+        for job in jobs.iter() {
+            let ready_at = if job.next_attempt_at > now {
+                job.next_attempt_at
+            } else {
+                let sent: Vec<DateTime<Utc>> = recent
+                    .iter()
+                    .filter(|(_, n)| *n == job.number)
+                    .map(|(t, _)| *t)
+                    .collect();
+                if per_number == 0 || sent.len() < per_number {
+                    recent.push_back((now, job.number.clone()));
+                    return Due::Job(job.clone());
+                }
+                sent[sent.len() - per_number] + RATE_WINDOW
+            };
+            wake_at = Some(wake_at.map_or(ready_at, |t| t.min(ready_at)));
+        }
+        wake_at.map_or(Due::Idle, Due::At)
+    }
+
+    /// 记录一次发送的结果。成功或不可重试的失败时移出队列、写入历史并把结果交给提交方；
+    /// 临时错误在未达 send_max_attempts 时按退避重新排期，message_refs 为已发出分段的消息参考号
+    pub fn finish(
+        &self,
+        cfg: &Config,
+        job: &Job,
+        message_refs: Vec<Option<u8>>,
+        result: Result<(), SendError>,
+    ) {
+        let attempts = job.attempts + 1;
+        if let Err(e) = &result {
+            if e.is_transient() && attempts < cfg.send_max_attempts {
+                let backoff = INITIAL_BACKOFF
+                    .saturating_mul(1 << (attempts - 1).min(16))
+                    .min(MAX_BACKOFF);
+                {
+                    let mut jobs = self.jobs.lock().unwrap();
+                    if let Some(j) = jobs.iter_mut().find(|j| j.id == job.id) {
+                        j.attempts = attempts;
+                        j.message_refs = message_refs;
+                        j.next_attempt_at = Utc::now() + backoff;
+                        j.last_error = Some(e.to_string());
+                    }
+                    if let Err(e) = persist(&jobs) {
+                        tracing::error!(err = %e, "persist send queue failed");
+                    }
+                }
+                tracing::warn!(
+                    id = job.id,
+                    number = %job.number,
+                    attempts,
+                    retry_in_secs = backoff.as_secs(),
+                    err = %e,
+                    "send SMS failed, will retry"
+                );
+                if let Some(hid) = job.history_id {
+                    if let Err(e) = self.history.outbound_retrying(hid, &e.to_string()) {
+                        tracing::error!(err = %e, "record outbound SMS failed");
+                    }
+                }
+                return;
+            }
+        }
+        {
+            let mut jobs = self.jobs.lock().unwrap();
+            jobs.retain(|j| j.id != job.id);
+            if let Err(e) = persist(&jobs) {
+                tracing::error!(err = %e, "persist send queue failed");
+            }
+        }
+        self.settle(job, message_refs, result);
+    }
+
+    /// 最终结果：写入短信历史并交给等待中的提交方
+    fn settle(&self, job: &Job, message_refs: Vec<Option<u8>>, result: Result<(), SendError>) {
+        match &result {
+            Ok(()) => tracing::info!(
+                id = job.id,
+                number = %job.number,
+                segments = message_refs.len(),
+                message_refs = ?message_refs,
+                "发送成功"
+            ),
+            Err(e) => tracing::warn!(id = job.id, number = %job.number, err = %e, "send SMS failed"),
+        }
+        if let Some(hid) = job.history_id {
+            let recorded = match &result {
+                Ok(()) => self.history.complete_outbound(hid, Ok(&message_refs)),
+                Err(e) => self.history.complete_outbound(hid, Err(&e.to_string())),
+            };
+            if let Err(e) = recorded {
+                tracing::error!(err = %e, "record outbound SMS failed");
+            }
+        }
+        let outcome = result.map(|()| Sent {
+            id: job.history_id,
+            segments: message_refs.len(),
+            message_refs,
+        });
+        // 提交方可能已不再等待
+        if let Some(tx) = self.waiters.lock().unwrap().remove(&job.id) {
+            let _ = tx.send(outcome);
+        }
+    }
+
+    pub fn stats(&self) -> Stats {
+        let now = Utc::now();
+        let sent_last_minute = self
+            .recent
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(t, _)| now - *t < RATE_WINDOW)
+            .count();
+        let jobs = self.jobs.lock().unwrap().clone();
+        Stats {
+            depth: jobs.len(),
+            sent_last_minute,
+            jobs,
+        }
+    }
+}
+
+/// 先写临时文件再改名，避免写到一半断电导致文件损坏
+fn persist(jobs: &[Job]) -> anyhow::Result<()> {
+    let tmp = format!("{}.tmp", QUEUE_FILE);
+    fs::write(&tmp, serde_json::to_string_pretty(jobs)?)?;
+    fs::rename(&tmp, QUEUE_FILE)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_rejects_bad_numbers() {
+        assert!(check("+8613800138000", "hi").is_ok());
+        assert!(check(&"1".repeat(20), "hi").is_ok());
+        assert!(check(&format!("+{}", "1".repeat(20)), "hi").is_ok());
+        assert!(matches!(check(&"1".repeat(21), "hi"), Err(SendError::Invalid(_))));
+        assert!(matches!(check("+", "hi"), Err(SendError::Invalid(_))));
+        assert!(matches!(check("138-0013", "hi"), Err(SendError::Invalid(_))));
+        assert!(matches!(check("10086", ""), Err(SendError::Invalid(_))));
+    }
+}
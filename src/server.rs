@@ -0,0 +1,126 @@
+// HTTP 服务监听：TCP（IPv4/IPv6，可选 TLS）或 Unix 域套接字
+// 明文 TCP 直接交给 axum::serve；TLS 与 Unix 套接字由这里逐个接受连接，再交给 hyper 处理
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Context;
+use axum::extract::ConnectInfo;
+use axum::{Extension, Router};
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use hyper_util::server::conn::auto;
+use hyper_util::service::TowerToHyperService;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{TcpListener, UnixListener};
+use tokio_rustls::rustls::pki_types::pem::PemObject;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use tokio_rustls::rustls::{self, ServerConfig};
+use tokio_rustls::TlsAcceptor;
+
+use crate::config::Config;
+
+/// TLS 握手超时，避免半开连接一直占用任务
+const TLS_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+/// accept 出错（如文件描述符耗尽 EMFILE/ENFILE）后等待一会再重试，避免空转占满 CPU；
+/// 明文 TCP 由 axum::serve 自行处理
+const ACCEPT_ERROR_DELAY: Duration = Duration::from_millis(100);
+
+/// 按配置监听并处理请求，直到监听出错
+pub async fn serve(cfg: &Config, app: Router) -> anyhow::Result<()> {
+    if let Some(path) = &cfg.unix_socket {
+        return serve_unix(path, app).await;
+    }
+    let listener = TcpListener::bind((cfg.listen_addr.as_str(), cfg.listen_port))
+        .await
+        .with_context(|| format!("bind {}:{} failed", cfg.listen_addr, cfg.listen_port))?;
+    let addr = listener.local_addr()?;
+    match (&cfg.tls_cert, &cfg.tls_key) {
+        (Some(cert), Some(key)) => {
+            let acceptor = tls_acceptor(cert, key)?;
+            tracing::info!(%addr, "listening (https)");
+            loop {
+                let (stream, peer) = match listener.accept().await {
+                    Ok(conn) => conn,
+                    Err(e) => {
+                        tracing::warn!(err = %e, "accept failed");
+                        tokio::time::sleep(ACCEPT_ERROR_DELAY).await;
+                        continue;
+                    }
+                };
+                let acceptor = acceptor.clone();
+                // 与 axum::serve 一样提供 ConnectInfo，登录限速按客户端地址区分
+                let app = app.clone().layer(Extension(ConnectInfo(peer)));
+                tokio::spawn(async move {
+                    match tokio::time::timeout(TLS_HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                        Ok(Ok(stream)) => serve_connection(stream, app).await,
+                        Ok(Err(e)) => tracing::debug!(%peer, err = %e, "TLS 握手失败"),
+                        Err(_) => tracing::debug!(%peer, "TLS 握手超时"),
+                    }
+                });
+            }
+        }
+        _ => {
+            tracing::info!(%addr, "listening (http)");
+            axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await?;
+            Ok(())
+        }
+    }
+}
+
+/// 监听 Unix 域套接字；启动时删除上次遗留的套接字文件
+async fn serve_unix(path: &str, app: Router) -> anyhow::Result<()> {
+    match std::fs::remove_file(path) {
+        Ok(()) => tracing::debug!(path, "removed stale socket"),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e).with_context(|| format!("remove {} failed", path)),
+    }
+    let listener = UnixListener::bind(path).with_context(|| format!("bind {} failed", path))?;
+    tracing::info!(path, "listening (unix socket)");
+    loop {
+        let (stream, _) = match listener.accept().await {
+            Ok(conn) => conn,
+            Err(e) => {
+                tracing::warn!(err = %e, "accept failed");
+                tokio::time::sleep(ACCEPT_ERROR_DELAY).await;
+                continue;
+            }
+        };
+        tokio::spawn(serve_connection(stream, app.clone()));
+    }
+}
+
+/// 在一条已建立的连接上处理 HTTP/1.1 或 HTTP/2 请求
+async fn serve_connection<S>(stream: S, app: Router)
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let service = TowerToHyperService::new(app);
+    if let Err(e) = auto::Builder::new(TokioExecutor::new())
+        .serve_connection_with_upgrades(TokioIo::new(stream), service)
+        .await
+    {
+        tracing::debug!(err = %e, "connection closed with error");
+    }
+}
+
+/// 读取 PEM 证书链与私钥，构造 TLS 接受器
+fn tls_acceptor(cert: &str, key: &str) -> anyhow::Result<TlsAcceptor> {
+    let certs = CertificateDer::pem_file_iter(cert)
+        .and_then(|it| it.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| anyhow::anyhow!("read tls_cert {} failed: {}", cert, e))?;
+    if certs.is_empty() {
+        anyhow::bail!("tls_cert {} 中没有证书", cert);
+    }
+    let key = PrivateKeyDer::from_pem_file(key)
+        .map_err(|e| anyhow::anyhow!("read tls_key {} failed: {}", key, e))?;
+    let mut config = ServerConfig::builder_with_provider(Arc::new(
+        rustls::crypto::ring::default_provider(),
+    ))
+    .with_safe_default_protocol_versions()?
+    .with_no_client_auth()
+    .with_single_cert(certs, key)
+    .context("invalid TLS certificate or key")?;
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
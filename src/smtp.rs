@@ -0,0 +1,119 @@
+// SMTP 邮件推送：短信正文以 UTF-8 纯文本邮件发出，短信时间戳写入 Date 与 X-SMS-Timestamp 头
+
+use std::time::SystemTime;
+
+use async_trait::async_trait;
+use lettre::message::header::{ContentType, Header, HeaderName, HeaderValue};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+
+use crate::config::{SmtpConfig, SmtpTls};
+use crate::notify::{Notification, Notifier};
+
+/// 自定义头：短信的服务中心时间戳（RFC 3339）
+#[derive(Clone)]
+struct SmsTimestamp(String);
+
+impl Header for SmsTimestamp {
+    fn name() -> HeaderName {
+        HeaderName::new_from_ascii_str("X-SMS-Timestamp")
+    }
+
+    fn parse(s: &str) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(Self(s.to_string()))
+    }
+
+    fn display(&self) -> HeaderValue {
+        HeaderValue::new(Self::name(), self.0.clone())
+    }
+}
+
+/// 自定义头：短信发送方号码
+#[derive(Clone)]
+struct SmsSender(String);
+
+impl Header for SmsSender {
+    fn name() -> HeaderName {
+        HeaderName::new_from_ascii_str("X-SMS-Sender")
+    }
+
+    fn parse(s: &str) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(Self(s.to_string()))
+    }
+
+    fn display(&self) -> HeaderValue {
+        HeaderValue::new(Self::name(), self.0.clone())
+    }
+}
+
+/// SMTP 渠道
+pub struct Smtp {
+    cfg: SmtpConfig,
+}
+
+impl Smtp {
+    pub fn new(cfg: SmtpConfig) -> Self {
+        Self { cfg }
+    }
+
+    fn transport(&self) -> anyhow::Result<AsyncSmtpTransport<Tokio1Executor>> {
+        let cfg = &self.cfg;
+        let (builder, default_port) = match cfg.tls {
+            SmtpTls::Implicit => (AsyncSmtpTransport::<Tokio1Executor>::relay(&cfg.host)?, 465),
+            SmtpTls::Starttls => (
+                AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&cfg.host)?,
+                587,
+            ),
+            SmtpTls::None => (
+                AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&cfg.host),
+                25,
+            ),
+        };
+        let mut builder = builder.port(cfg.port.unwrap_or(default_port));
+        if !cfg.username.is_empty() {
+            builder = builder.credentials(Credentials::new(
+                cfg.username.clone(),
+                cfg.password.clone(),
+            ));
+        }
+        Ok(builder.build())
+    }
+
+    /// 主题模板支持 {{sender}} 与 {{title}}
+    fn subject(&self, n: &Notification) -> String {
+        let subject = self
+            .cfg
+            .subject_template
+            .replace("{{sender}}", &n.sender)
+            .replace("{{title}}", &n.title);
+        if n.emergency {
+            format!("[紧急] {}", subject)
+        } else {
+            subject
+        }
+    }
+}
+
+#[async_trait]
+impl Notifier for Smtp {
+    async fn send(&self, n: &Notification) -> anyhow::Result<()> {
+        let mut builder = Message::builder()
+            .from(self.cfg.from.parse()?)
+            .subject(self.subject(n))
+            .header(ContentType::TEXT_PLAIN)
+            .header(SmsSender(n.sender.clone()));
+        for to in &self.cfg.to {
+            builder = builder.to(to.parse()?);
+        }
+        if let Some(ts) = n.timestamp {
+            builder = builder
+                .date(SystemTime::from(ts))
+                .header(SmsTimestamp(ts.to_rfc3339()));
+        }
+        let email = builder.body(n.body.clone())?;
+
+        tracing::debug!(host = %self.cfg.host, to = ?self.cfg.to, "sending email");
+        self.transport()?.send(email).await?;
+        Ok(())
+    }
+}
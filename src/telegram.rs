@@ -0,0 +1,263 @@
+// Telegram Bot API 推送：https://core.telegram.org/bots/api#sendmessage
+// api_base 可指向自建 Bot API 服务器或本地测试桩
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use crate::config::{TelegramConfig, TelegramParseMode};
+use crate::notify::{self, Level, Notification, Notifier};
+
+#[derive(Serialize)]
+struct SendMessage<'a> {
+    chat_id: &'a str,
+    text: String,
+    parse_mode: &'static str,
+    /// 静默推送（passive 级别，如免打扰时段内）
+    disable_notification: bool,
+}
+
+/// Bot API 统一响应结构，失败时 description 给出原因
+#[derive(Deserialize)]
+struct ApiResponse {
+    ok: bool,
+    description: Option<String>,
+}
+
+/// HTML 模式只需转义 < > &
+fn escape_html(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '&' => out.push_str("&amp;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// MarkdownV2 模式下所有保留字符都需反斜杠转义
+fn escape_markdown_v2(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        if "_*[]()~`>#+-=|{}.!\\".contains(c) {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// sendMessage 单条消息的长度上限（按 UTF-16 码元计）
+const MAX_MESSAGE_LEN: usize = 4096;
+
+fn utf16_len(s: &str) -> usize {
+    s.encode_utf16().count()
+}
+
+/// Telegram 渠道
+pub struct Telegram {
+    cfg: TelegramConfig,
+}
+
+impl Telegram {
+    pub fn new(cfg: TelegramConfig) -> Self {
+        Self { cfg }
+    }
+
+    /// 标题加粗，正文原样转义；紧急通知在标题前加标记。
+    /// 超过单条长度上限的正文（如长汇总）拆成多条，尽量在换行处断开，转义序列不会被拆开
+    fn format(&self, n: &Notification) -> Vec<String> {
+        let title = if n.emergency {
+            format!("‼️ {}", n.title)
+        } else {
+            n.title.clone()
+        };
+        let (header, escape): (String, fn(&str) -> String) = match self.cfg.parse_mode {
+            TelegramParseMode::Html => (format!("<b>{}</b>\n", escape_html(&title)), escape_html),
+            TelegramParseMode::MarkdownV2 => (
+                format!("*{}*\n", escape_markdown_v2(&title)),
+                escape_markdown_v2,
+            ),
+        };
+        let mut parts = Vec::new();
+        let mut current = header;
+        // 当前这条中不能断开的前缀（标题）长度
+        let mut keep = current.len();
+        let mut buf = [0u8; 4];
+        for c in n.body.chars() {
+            let escaped = escape(c.encode_utf8(&mut buf));
+            if utf16_len(&current) + utf16_len(&escaped) > MAX_MESSAGE_LEN {
+                let rest = match current.rfind('\n').filter(|&i| i >= keep.max(current.len() / 2)) {
+                    Some(i) => {
+                        let rest = current[i + 1..].to_string();
+                        current.truncate(i);
+                        rest
+                    }
+                    None => String::new(),
+                };
+                parts.push(std::mem::replace(&mut current, rest));
+                keep = 0;
+            }
+            current.push_str(&escaped);
+        }
+        parts.push(current);
+        parts
+    }
+
+    async fn send_message(&self, n: &Notification, text: String) -> anyhow::Result<()> {
+        let url = format!(
+            "{}/bot{}/sendMessage",
+            self.cfg.api_base.trim_end_matches('/'),
+            self.cfg.bot_token
+        );
+        let msg = SendMessage {
+            chat_id: &self.cfg.chat_id,
+            text,
+            parse_mode: match self.cfg.parse_mode {
+                TelegramParseMode::Html => "HTML",
+                TelegramParseMode::MarkdownV2 => "MarkdownV2",
+            },
+            disable_notification: n.level == Level::Passive,
+        };
+
+        tracing::debug!(chat_id = %self.cfg.chat_id, "pushing to Telegram");
+        // URL 中带有 bot token，错误信息会写入发件箱与短信历史，去掉 URL 后再返回
+        let res = notify::http_client()
+            .post(&url)
+            .json(&msg)
+            .send()
+            .await
+            .map_err(reqwest::Error::without_url)?;
+        let status = res.status();
+        let body: ApiResponse = match res.json().await {
+            Ok(body) => body,
+            Err(e) => anyhow::bail!("Telegram sendMessage failed ({}): {}", status, e.without_url()),
+        };
+        if !body.ok {
+            anyhow::bail!(
+                "Telegram sendMessage failed ({}): {}",
+                status,
+                body.description.unwrap_or_default()
+            );
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Notifier for Telegram {
+    async fn send(&self, n: &Notification) -> anyhow::Result<()> {
+        self.send_remaining(n, &mut Vec::new()).await
+    }
+
+    /// 拆成多条时依次发送，已发出的分条按序号记录，发件箱重试时从失败的一条继续
+    async fn send_remaining(&self, n: &Notification, delivered: &mut Vec<String>) -> anyhow::Result<()> {
+        let parts = self.format(n);
+        let total = parts.len();
+        for (i, text) in parts.into_iter().enumerate() {
+            let part = format!("part {}/{}", i + 1, total);
+            if delivered.contains(&part) {
+                continue;
+            }
+            self.send_message(n, text).await?;
+            delivered.push(part);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{BarkOptions, TelegramParseMode};
+
+    const TOKEN: &str = "123456:SecretBotToken";
+
+    fn telegram(api_base: &str, parse_mode: TelegramParseMode) -> Telegram {
+        Telegram::new(TelegramConfig {
+            bot_token: TOKEN.to_string(),
+            chat_id: "42".to_string(),
+            api_base: api_base.to_string(),
+            parse_mode,
+        })
+    }
+
+    fn notification(body: &str) -> Notification {
+        Notification {
+            title: "SMS from 10086".to_string(),
+            body: body.to_string(),
+            emergency: false,
+            level: Level::Active,
+            sender: "10086".to_string(),
+            timestamp: None,
+            indices: Vec::new(),
+            bark: BarkOptions::default(),
+            history_id: None,
+        }
+    }
+
+    #[test]
+    fn short_message_is_one_part() {
+        let parts = telegram("", TelegramParseMode::Html).format(&notification("a < b"));
+        assert_eq!(parts, vec!["<b>SMS from 10086</b>\na &lt; b".to_string()]);
+    }
+
+    #[test]
+    fn long_message_is_split_within_limit() {
+        // 每行 100 个字符，共 100 行，转义后超过上限
+        let line = format!("{}\n", "a.b&c<".repeat(16) + "1234");
+        let body = line.repeat(100);
+        for mode in [TelegramParseMode::Html, TelegramParseMode::MarkdownV2] {
+            let parts = telegram("", mode).format(&notification(&body));
+            assert!(parts.len() > 1);
+            assert!(parts.iter().all(|p| utf16_len(p) <= MAX_MESSAGE_LEN));
+            // 在换行处断开，各分条不以半行开头
+            assert!(parts[1..].iter().all(|p| p.starts_with('a')));
+        }
+        let parts = telegram("", TelegramParseMode::Html).format(&notification(&body));
+        let joined = parts.join("\n");
+        assert_eq!(joined.matches("&amp;").count(), 1600);
+        assert_eq!(joined.matches("&lt;").count(), 1600);
+
+        // 没有换行的长正文按长度断开，中文按 UTF-16 计
+        let parts = telegram("", TelegramParseMode::Html).format(&notification(&"短信".repeat(3000)));
+        assert_eq!(parts.len(), 2);
+        assert!(parts.iter().all(|p| utf16_len(p) <= MAX_MESSAGE_LEN));
+    }
+
+    #[tokio::test]
+    async fn errors_do_not_contain_bot_token() {
+        let server = notify::http_stub("502 Bad Gateway", "<html>bad gateway</html>").await;
+        let err = telegram(&server, TelegramParseMode::Html)
+            .send(&notification("hello"))
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("502"), "{}", err);
+        assert!(!err.contains(TOKEN), "{}", err);
+
+        let err = telegram("http://127.0.0.1:1", TelegramParseMode::Html)
+            .send(&notification("hello"))
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(!err.contains(TOKEN), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn api_error_is_reported() {
+        let server = notify::http_stub(
+            "400 Bad Request",
+            r#"{"ok":false,"description":"Bad Request: chat not found"}"#,
+        )
+        .await;
+        let err = telegram(&server, TelegramParseMode::Html)
+            .send(&notification("hello"))
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("chat not found"), "{}", err);
+    }
+}
@@ -1,21 +1,169 @@
 use axum::{
-    extract::State,
-    response::Html,
+    extract::{ConnectInfo, Path, Query, Request, State},
+    http::{header, Method, StatusCode},
+    middleware::{self, Next},
+    response::{Html, IntoResponse, Redirect, Response},
     routing::{get, post},
     Json, Router,
 };
+use chrono::{DateTime, NaiveDate, Utc};
+use std::net::SocketAddr;
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
 
-use crate::config::Config;
+use crate::auth::{self, LoginThrottle, Sessions};
+use crate::cms;
+use crate::config::{Config, SharedConfig};
+use crate::history::{self, Direction, Filter, History};
 use crate::modem;
+use crate::outbox::{self, Outbox};
+use crate::quiet::Zone;
+use crate::sendqueue::{self, SendQueue};
 
-pub fn router(cfg: Config) -> Router {
-    Router::new()
+/// POST /send 等待发送结果的时间，超过后返回 202，短信留在队列中继续发送
+const SEND_WAIT: Duration = Duration::from_secs(60);
+/// 历史查询每页默认条数与上限
+const DEFAULT_PER_PAGE: u32 = 50;
+const MAX_PER_PAGE: u32 = 200;
+
+#[derive(Clone)]
+struct AppState {
+    cfg: SharedConfig,
+    outbox: Outbox,
+    history: History,
+    queue: SendQueue,
+    sessions: Sessions,
+    logins: LoginThrottle,
+}
+
+pub fn router(cfg: SharedConfig, outbox: Outbox, history: History, queue: SendQueue) -> Router {
+    let state = AppState {
+        cfg,
+        outbox,
+        history,
+        queue,
+        sessions: Sessions::default(),
+        logins: LoginThrottle::default(),
+    };
+    // 除登录与健康检查外的所有路由都需要认证
+    let protected = Router::new()
         .route("/", get(index_page))
         .route("/settings", get(settings_page))
         .route("/config", get(get_cfg))
         .route("/config", post(set_cfg))
         .route("/send", post(send_sms))
-        .with_state(cfg)
+        .route("/send/queue", get(get_send_queue))
+        .route("/outbox", get(get_outbox))
+        .route("/history", get(history_page))
+        .route("/api/messages", get(list_messages))
+        .route("/api/conversations", get(list_conversations))
+        .route("/api/outbound/:id", get(get_outbound))
+        .route("/logout", post(logout))
+        .route_layer(middleware::from_fn_with_state(state.clone(), require_auth));
+    Router::new()
+        .route("/healthz", get(healthz))
+        .route("/login", get(login_page).post(login))
+        .merge(protected)
+        .with_state(state)
+}
+
+/// 未认证时：浏览器打开页面跳转到登录页，其余请求返回 401
+async fn require_auth(State(state): State<AppState>, req: Request, next: Next) -> Response {
+    if auth::authorized(&state.cfg.get().auth, &state.sessions, req.headers()) {
+        return next.run(req).await;
+    }
+    let wants_html = req.method() == Method::GET
+        && req
+            .headers()
+            .get(header::ACCEPT)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.contains("text/html"));
+    if wants_html {
+        Redirect::to("/login").into_response()
+    } else {
+        (
+            StatusCode::UNAUTHORIZED,
+            [(header::WWW_AUTHENTICATE, "Bearer")],
+            "unauthorized",
+        )
+            .into_response()
+    }
+}
+
+async fn healthz() -> &'static str {
+    "ok"
+}
+
+async fn login_page() -> Html<&'static str> {
+    Html(LOGIN_HTML)
+}
+
+#[derive(Deserialize)]
+struct LoginForm {
+    username: String,
+    password: String,
+}
+
+/// 同一客户端对同一用户名连续失败后限速；客户端地址取自 TCP 连接，Unix 套接字上为 None
+async fn login(
+    State(state): State<AppState>,
+    peer: Option<ConnectInfo<SocketAddr>>,
+    Json(form): Json<LoginForm>,
+) -> Response {
+    let cfg = state.cfg.get();
+    // 双栈监听时 IPv4 客户端显示为 ::ffff:a.b.c.d，统一还原为 IPv4
+    let client = peer.map(|ConnectInfo(addr)| addr.ip().to_canonical());
+    // 连续失败后限速，限速期间不校验密码
+    if let Some(wait) = state.logins.check(client, &form.username) {
+        let secs = wait.as_secs() + 1;
+        tracing::warn!(username = %form.username, client = ?client, retry_after = secs, "login throttled");
+        return (
+            StatusCode::TOO_MANY_REQUESTS,
+            [(header::RETRY_AFTER, secs.to_string())],
+            format!("登录失败次数过多，请 {} 秒后再试", secs),
+        )
+            .into_response();
+    }
+    if !auth::login(&cfg.auth, &form.username, &form.password) {
+        state.logins.failed(client, &form.username);
+        tracing::warn!(username = %form.username, client = ?client, "login failed");
+        return (StatusCode::UNAUTHORIZED, "用户名或密码错误").into_response();
+    }
+    state.logins.succeeded(client, &form.username);
+    let ttl = cfg.auth.session_ttl_secs;
+    let id = state
+        .sessions
+        .create(&form.username, std::time::Duration::from_secs(ttl));
+    tracing::info!(username = %form.username, "logged in");
+    (
+        StatusCode::NO_CONTENT,
+        [(
+            header::SET_COOKIE,
+            format!(
+                "{}={}; Path=/; Max-Age={}; HttpOnly; SameSite=Strict{}",
+                auth::SESSION_COOKIE,
+                id,
+                ttl,
+                // 启用 HTTPS 时 Cookie 只通过加密连接发送
+                if cfg.tls_cert.is_some() { "; Secure" } else { "" }
+            ),
+        )],
+    )
+        .into_response()
+}
+
+async fn logout(State(state): State<AppState>, headers: axum::http::HeaderMap) -> Response {
+    if let Some(id) = auth::session_id(&headers) {
+        state.sessions.remove(&id);
+    }
+    (
+        StatusCode::NO_CONTENT,
+        [(
+            header::SET_COOKIE,
+            format!("{}=; Path=/; Max-Age=0; HttpOnly; SameSite=Strict", auth::SESSION_COOKIE),
+        )],
+    )
+        .into_response()
 }
 
 async fn index_page() -> Html<&'static str> {
@@ -26,22 +174,255 @@ async fn settings_page() -> Html<&'static str> {
     Html(SETTINGS_HTML)
 }
 
-async fn get_cfg(State(cfg): State<Config>) -> Json<Config> {
+async fn history_page() -> Html<&'static str> {
+    Html(HISTORY_HTML)
+}
+
+async fn get_cfg(State(state): State<AppState>) -> Json<Config> {
     tracing::debug!("GET /config");
-    Json(cfg)
+    Json(Config::clone(&state.cfg.get()))
 }
 
-async fn set_cfg(State(_cfg): State<Config>, Json(new): Json<Config>) {
+/// 保存并立即生效：渠道、规则、认证等下一次使用时即读取新配置，串口相关设置变更时收信任务重开串口
+async fn set_cfg(
+    State(state): State<AppState>,
+    Json(new): Json<Config>,
+) -> Result<(), (StatusCode, String)> {
+    if let Err(e) = new.validate() {
+        tracing::warn!(err = %e, "POST /config rejected");
+        return Err((StatusCode::UNPROCESSABLE_ENTITY, e.to_string()));
+    }
     tracing::info!("POST /config, saving");
     new.save();
+    let old = state.cfg.get();
+    if old.listen_addr != new.listen_addr
+        || old.listen_port != new.listen_port
+        || old.unix_socket != new.unix_socket
+        || old.tls_cert != new.tls_cert
+        || old.tls_key != new.tls_key
+    {
+        tracing::warn!("监听设置已保存，重启后生效");
+    }
+    state.cfg.set(new);
+    tracing::info!("config applied");
+    Ok(())
+}
+
+/// POST /send 的结果
+#[derive(Serialize)]
+struct SendResult {
+    /// sent、failed 或 queued（仍在发送队列中）
+    status: &'static str,
+    /// 短信历史中的发信记录 ID，可用于查询发送与送达状态
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<i64>,
+    /// 分段数
+    #[serde(skip_serializing_if = "Option::is_none")]
+    segments: Option<usize>,
+    /// 各段的消息参考号
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message_refs: Option<Vec<Option<u8>>>,
+    /// 失败类别：invalid / port / timeout / cms_error / modem_error / queue / expired
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<&'static str>,
+    /// +CMS ERROR 错误码
+    #[serde(skip_serializing_if = "Option::is_none")]
+    code: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<String>,
 }
 
-async fn send_sms(State(cfg): State<Config>, Json((number, text)): Json<(String, String)>) {
+/// 失败对应的 HTTP 状态码：请求有误 400，号码或内容被拒 422，modem/SIM/网络暂不可用 503，超时 504，
+/// 无法写入队列 500，其他 modem 错误 502
+fn send_error_status(e: &modem::SendError) -> StatusCode {
+    match e {
+        modem::SendError::Invalid(_) => StatusCode::BAD_REQUEST,
+        modem::SendError::Port(_) | modem::SendError::Expired => StatusCode::SERVICE_UNAVAILABLE,
+        modem::SendError::Timeout => StatusCode::GATEWAY_TIMEOUT,
+        modem::SendError::Queue(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        modem::SendError::Cms { code: Some(code), .. } if cms::is_rejected(*code) => {
+            StatusCode::UNPROCESSABLE_ENTITY
+        }
+        modem::SendError::Cms { code: Some(code), .. }
+            if cms::is_transient(*code) || cms::is_unavailable(*code) =>
+        {
+            StatusCode::SERVICE_UNAVAILABLE
+        }
+        modem::SendError::Cms { .. } | modem::SendError::Modem(_) => StatusCode::BAD_GATEWAY,
+    }
+}
+
+fn send_failed(id: Option<i64>, e: modem::SendError) -> (StatusCode, Json<SendResult>) {
+    let code = match &e {
+        modem::SendError::Cms { code, .. } => *code,
+        _ => None,
+    };
+    (
+        send_error_status(&e),
+        Json(SendResult {
+            status: "failed",
+            id,
+            segments: None,
+            message_refs: None,
+            error: Some(e.kind()),
+            code,
+            reason: Some(match e {
+                modem::SendError::Cms { reason, .. } => reason,
+                other => other.to_string(),
+            }),
+        }),
+    )
+}
+
+/// 写入发送队列后等待结果；受速率限制或等待重试超过 SEND_WAIT 时返回 202，短信继续留在队列中
+async fn send_sms(
+    State(state): State<AppState>,
+    Json((number, text)): Json<(String, String)>,
+) -> (StatusCode, Json<SendResult>) {
     tracing::info!(number = %number, "接收到发送的命令");
-    modem::send_sms(&cfg, &number, &text).await;
+    let ticket = match state.queue.submit(number.trim(), &text) {
+        Ok(ticket) => ticket,
+        Err(e) => return send_failed(None, e),
+    };
+    match tokio::time::timeout(SEND_WAIT, ticket.result).await {
+        Ok(Ok(Ok(sent))) => (
+            StatusCode::OK,
+            Json(SendResult {
+                status: "sent",
+                id: sent.id,
+                segments: Some(sent.segments),
+                message_refs: Some(sent.message_refs),
+                error: None,
+                code: None,
+                reason: None,
+            }),
+        ),
+        Ok(Ok(Err(e))) => send_failed(ticket.history_id, e),
+        Ok(Err(_)) | Err(_) => (
+            StatusCode::ACCEPTED,
+            Json(SendResult {
+                status: "queued",
+                id: ticket.history_id,
+                segments: None,
+                message_refs: None,
+                error: None,
+                code: None,
+                reason: None,
+            }),
+        ),
+    }
+}
+
+async fn get_send_queue(State(state): State<AppState>) -> Json<sendqueue::Stats> {
+    tracing::debug!("GET /send/queue");
+    Json(state.queue.stats())
+}
+
+async fn get_outbox(State(state): State<AppState>) -> Json<outbox::Stats> {
+    tracing::debug!("GET /outbox");
+    Json(state.outbox.stats())
+}
+
+/// GET /api/messages 与 /api/conversations 的查询参数
+#[derive(Deserialize)]
+struct HistoryQuery {
+    direction: Option<Direction>,
+    /// 对方号码，精确匹配
+    #[serde(alias = "number")]
+    sender: Option<String>,
+    /// 起止时间：RFC 3339，或 YYYY-MM-DD（按配置的时区，to 含当天）
+    from: Option<String>,
+    to: Option<String>,
+    /// 正文或号码包含的文字
+    q: Option<String>,
+    page: Option<u32>,
+    per_page: Option<u32>,
+}
+
+impl HistoryQuery {
+    fn filter(&self, zone: Zone) -> Result<Filter, (StatusCode, String)> {
+        let non_empty = |s: &Option<String>| s.clone().filter(|s| !s.trim().is_empty());
+        Ok(Filter {
+            direction: self.direction,
+            number: non_empty(&self.sender),
+            since: non_empty(&self.from)
+                .map(|s| parse_bound(&s, zone, false))
+                .transpose()?,
+            until: non_empty(&self.to)
+                .map(|s| parse_bound(&s, zone, true))
+                .transpose()?,
+            text: non_empty(&self.q),
+        })
+    }
+
+    fn paging(&self) -> (u32, u32) {
+        let per_page = self
+            .per_page
+            .unwrap_or(DEFAULT_PER_PAGE)
+            .clamp(1, MAX_PER_PAGE);
+        (self.page.unwrap_or(1).max(1), per_page)
+    }
+}
+
+/// 解析时间范围的一端；只给日期时 from 取当天零点，to 取次日零点
+fn parse_bound(s: &str, zone: Zone, end: bool) -> Result<DateTime<Utc>, (StatusCode, String)> {
+    if let Ok(t) = DateTime::parse_from_rfc3339(s) {
+        return Ok(t.with_timezone(&Utc));
+    }
+    let date = NaiveDate::parse_from_str(s, "%Y-%m-%d").map_err(|_| {
+        (
+            StatusCode::BAD_REQUEST,
+            format!("无法识别的时间 {:?}，应为 RFC 3339 或 YYYY-MM-DD", s),
+        )
+    })?;
+    let date = if end { date.succ_opt().unwrap_or(date) } else { date };
+    Ok(zone.to_utc(date.and_hms_opt(0, 0, 0).unwrap_or_default()))
+}
+
+fn internal_error(e: anyhow::Error) -> (StatusCode, String) {
+    tracing::error!(err = %e, "query SMS history failed");
+    (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+}
+
+async fn list_messages(
+    State(state): State<AppState>,
+    Query(query): Query<HistoryQuery>,
+) -> Result<Json<history::Page<history::Message>>, (StatusCode, String)> {
+    let filter = query.filter(state.cfg.get().zone())?;
+    let (page, per_page) = query.paging();
+    state
+        .history
+        .messages(&filter, page, per_page)
+        .map(Json)
+        .map_err(internal_error)
+}
+
+async fn list_conversations(
+    State(state): State<AppState>,
+    Query(query): Query<HistoryQuery>,
+) -> Result<Json<history::Page<history::Conversation>>, (StatusCode, String)> {
+    let filter = query.filter(state.cfg.get().zone())?;
+    let (page, per_page) = query.paging();
+    state
+        .history
+        .conversations(&filter, page, per_page)
+        .map(Json)
+        .map_err(internal_error)
+}
+
+/// 一条发信记录及各段的送达状态
+async fn get_outbound(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+) -> Result<Json<history::Message>, (StatusCode, String)> {
+    match state.history.outbound(id) {
+        Ok(Some(message)) => Ok(Json(message)),
+        Ok(None) => Err((StatusCode::NOT_FOUND, "no such outbound message".into())),
+        Err(e) => Err(internal_error(e)),
+    }
 }
 
-// 简单双页面前端，黑白配色
+// 简单多页面前端，黑白配色
 const INDEX_HTML: &str = r#"<!DOCTYPE html>
 <html lang="zh-CN">
 <head>
@@ -324,7 +705,7 @@ const INDEX_HTML: &str = r#"<!DOCTYPE html>
 
     <div class="footer">
       <span>HTTP API: <code>/send</code> / <code>/config</code></span>
-      <a class="link" href="/settings">配置</a>
+      <span><a class="link" href="/history">历史</a> · <a class="link" href="/settings">配置</a> · <a class="link" id="logoutBtn">退出</a></span>
     </div>
   </div>
 
@@ -365,10 +746,15 @@ const INDEX_HTML: &str = r#"<!DOCTYPE html>
             },
             body: JSON.stringify([number, text])
           });
+          const result = await res.json().catch(function () { return null; });
           if (!res.ok) {
-            throw new Error('HTTP ' + res.status);
+            throw new Error(result && result.reason ? result.reason : 'HTTP ' + res.status);
+          }
+          if (result && result.status === 'queued') {
+            setStatus('success', '已加入发送队列，稍后发送');
+          } else {
+            setStatus('success', result && result.segments > 1 ? '已发送（' + result.segments + ' 段）' : '已发送');
           }
-          setStatus('success', '发送指令已提交（请查看日志确认结果）');
         } catch (e) {
           console.error(e);
           setStatus('error', '发送失败：' + (e.message || e));
@@ -389,6 +775,10 @@ const INDEX_HTML: &str = r#"<!DOCTYPE html>
       settingsBtn.addEventListener('click', function () {
         window.location.href = '/settings';
       });
+      document.getElementById('logoutBtn').addEventListener('click', async function () {
+        await fetch('/logout', { method: 'POST' });
+        window.location.href = '/login';
+      });
     })();
   </script>
 </body>
@@ -499,6 +889,7 @@ const SETTINGS_HTML: &str = r#"<!DOCTYPE html>
     }
     input[type="text"],
     input[type="number"],
+    select,
     textarea {
       width: 100%;
       border-radius: 10px;
@@ -520,6 +911,7 @@ const SETTINGS_HTML: &str = r#"<!DOCTYPE html>
       color: var(--fg-muted);
     }
     input:focus,
+    select:focus,
     textarea:focus {
       border-color: var(--accent);
       box-shadow: 0 0 0 1px rgba(255, 255, 255, 0.3);
@@ -651,23 +1043,60 @@ const SETTINGS_HTML: &str = r#"<!DOCTYPE html>
     <div class="section-label">Bark &amp; Security</div>
     <div class="grid">
       <div class="field">
-        <label for="bark_key">Bark Key</label>
-        <input id="bark_key" type="text" autocomplete="off" placeholder="Bark 推送 key">
+        <label for="bark_server">Bark 服务器</label>
+        <input id="bark_server" type="text" autocomplete="off" placeholder="https://api.day.app">
       </div>
       <div class="field">
-        <label for="aes_key">AES Key (16 字节)</label>
-        <input id="aes_key" type="text" autocomplete="off" placeholder="16 字节字符串">
+        <label for="bark_key">Bark Key（每行一个设备）</label>
+        <textarea id="bark_key" autocomplete="off" spellcheck="false" placeholder="Bark 推送 key"></textarea>
       </div>
       <div class="field">
-        <label for="aes_iv">AES IV (16 字节)</label>
-        <input id="aes_iv" type="text" autocomplete="off" placeholder="16 字节字符串">
+        <label for="aes_key">AES Key (16/24/32 字节)</label>
+        <input id="aes_key" type="text" autocomplete="off" placeholder="16、24 或 32 字节字符串">
       </div>
+      <div class="field">
+        <label for="aes_mode">AES 模式</label>
+        <select id="aes_mode">
+          <option value="cbc">CBC</option>
+          <option value="ecb">ECB</option>
+          <option value="gcm">GCM</option>
+        </select>
+      </div>
+    </div>
+    <div class="field">
+      <label for="bark_options">Bark 推送参数（JSON 对象）</label>
+      <textarea id="bark_options" spellcheck="false" placeholder='{"group": "{{sender}}", "icon": "https://...", "url": "https://...", "sound": "birdsong"}'></textarea>
+      <div class="note">可选 <code>sound</code>、<code>group</code>、<code>icon</code>、<code>url</code>、<code>copy</code>、<code>auto_copy</code>、<code>is_archive</code>、<code>badge</code>；字符串中可使用 <code>{{sender}}</code>、<code>{{title}}</code>。</div>
     </div>
     <div class="field">
       <label for="emergency_keywords">紧急关键词（每行一个）</label>
       <textarea id="emergency_keywords" placeholder="例如：&#10;违规停车&#10;验证码"></textarea>
       <div class="note">短信内容包含任一关键字时，将以紧急通知推送到 Bark。</div>
     </div>
+    <div class="field">
+      <label for="timezone">时区</label>
+      <input id="timezone" type="text" autocomplete="off" placeholder="Asia/Shanghai（留空使用系统时区）">
+    </div>
+    <div class="field">
+      <label for="quiet_hours">免打扰时段（JSON 数组）</label>
+      <textarea id="quiet_hours" spellcheck="false" placeholder='[{"name": "night", "from": "23:00", "to": "07:30", "action": "digest"}]'></textarea>
+      <div class="note"><code>action</code> 为 <code>demote</code>（静默推送）、<code>delay</code>（时段结束后推送）或 <code>digest</code>（时段结束后汇总推送）；可选 <code>days</code> 限定星期。紧急通知不受影响。</div>
+    </div>
+    <div class="field">
+      <label for="otp_patterns">验证码识别正则（每行一个）</label>
+      <textarea id="otp_patterns" spellcheck="false" placeholder="(?:验证码|校验码)[:：\s]*(?P&lt;code&gt;\d{4,8})"></textarea>
+      <div class="note">按顺序匹配，取名为 <code>code</code> 的捕获组（或第 1 个捕获组）；识别到的验证码写入通知标题，Bark 推送时自动复制。留空则不识别。</div>
+    </div>
+    <div class="field">
+      <label for="notifiers">其他通知渠道（JSON 数组）</label>
+      <textarea id="notifiers" spellcheck="false" placeholder='[{"name": "bark-2", "type": "bark", "enabled": true, "bark_key": "...", "aes_key": "...", "aes_mode": "cbc"}]'></textarea>
+      <div class="note">每个渠道需有唯一的 <code>name</code> 与 <code>type</code>；上面的 Bark Key 不为空时，另有一个名为 <code>bark</code> 的默认渠道。</div>
+    </div>
+    <div class="field">
+      <label for="rules">路由规则（JSON 数组）</label>
+      <textarea id="rules" spellcheck="false" placeholder='[{"name": "bank", "match": {"sender": {"prefix": "955"}}, "action": {"notifiers": ["bark"], "level": "timeSensitive", "stop": true}}]'></textarea>
+      <div class="note">按顺序匹配 <code>match</code>（<code>sender</code>、<code>body</code>、<code>time</code>、<code>modem</code>），命中后执行 <code>action</code>（<code>notifiers</code>、<code>level</code>、<code>bark</code>、<code>drop</code>、<code>stop</code>）。</div>
+    </div>
 
     <div class="section-label" style="margin-top: 10px;">Modem</div>
     <div class="grid">
@@ -679,6 +1108,17 @@ const SETTINGS_HTML: &str = r#"<!DOCTYPE html>
         <label for="baud_rate">波特率</label>
         <input id="baud_rate" type="number" min="1" step="1" placeholder="115200">
       </div>
+      <div class="field">
+        <label for="poll_interval_secs">兜底轮询间隔（秒）</label>
+        <input id="poll_interval_secs" type="number" min="1" step="1" placeholder="60">
+      </div>
+      <div class="field">
+        <label for="sms_mode">收信模式</label>
+        <select id="sms_mode">
+          <option value="text">文本模式（AT+CMGF=1）</option>
+          <option value="pdu">PDU 模式（AT+CMGF=0）</option>
+        </select>
+      </div>
     </div>
 
     <div class="row">
@@ -699,15 +1139,25 @@ const SETTINGS_HTML: &str = r#"<!DOCTYPE html>
   <script>
     (function () {
       const ids = [
+        'bark_server',
         'bark_key',
         'aes_key',
-        'aes_iv',
+        'aes_mode',
+        'bark_options',
         'emergency_keywords',
+        'timezone',
+        'quiet_hours',
+        'otp_patterns',
+        'notifiers',
+        'rules',
         'modem_device',
-        'baud_rate'
+        'baud_rate',
+        'poll_interval_secs',
+        'sms_mode'
       ];
 
       const el = {};
+      let loaded = {};
       ids.forEach(function (id) {
         el[id] = document.getElementById(id);
       });
@@ -727,24 +1177,77 @@ const SETTINGS_HTML: &str = r#"<!DOCTYPE html>
           .split(/[\r\n]+/)
           .map(function (s) { return s.trim(); })
           .filter(function (s) { return s.length > 0; });
+        // 已有设备保留其单独的 AES 设置（只能在 config.json 中配置）
+        const prevDevices = {};
+        (loaded.bark_key || []).forEach(function (d) { prevDevices[d.key] = d; });
+        const devices = el.bark_key.value
+          .split(/[\r\n]+/)
+          .map(function (s) { return s.trim(); })
+          .filter(function (s) { return s.length > 0; })
+          .map(function (key) { return prevDevices[key] || { key: key }; });
+        const notifiersRaw = el.notifiers.value.trim();
+        const notifiers = notifiersRaw ? JSON.parse(notifiersRaw) : [];
+        if (!Array.isArray(notifiers)) {
+          throw new Error('通知渠道必须是 JSON 数组');
+        }
+        const rulesRaw = el.rules.value.trim();
+        const rules = rulesRaw ? JSON.parse(rulesRaw) : [];
+        if (!Array.isArray(rules)) {
+          throw new Error('路由规则必须是 JSON 数组');
+        }
+        const quietRaw = el.quiet_hours.value.trim();
+        const quietHours = quietRaw ? JSON.parse(quietRaw) : [];
+        if (!Array.isArray(quietHours)) {
+          throw new Error('免打扰时段必须是 JSON 数组');
+        }
+        const otpPatterns = el.otp_patterns.value
+          .split(/[\r\n]+/)
+          .map(function (s) { return s.trim(); })
+          .filter(function (s) { return s.length > 0; });
+        const barkOptionsRaw = el.bark_options.value.trim();
+        const barkOptions = barkOptionsRaw ? JSON.parse(barkOptionsRaw) : {};
+        if (barkOptions === null || typeof barkOptions !== 'object' || Array.isArray(barkOptions)) {
+          throw new Error('Bark 推送参数必须是 JSON 对象');
+        }
         const baud = parseInt(el.baud_rate.value, 10);
-        return {
-          bark_key: el.bark_key.value.trim(),
+        const pollInterval = parseInt(el.poll_interval_secs.value, 10);
+        // 以加载到的配置为底，页面上没有的字段原样保留
+        return Object.assign({}, loaded, {
+          bark_server: el.bark_server.value.trim() || 'https://api.day.app',
+          bark_key: devices,
           aes_key: el.aes_key.value.trim(),
-          aes_iv: el.aes_iv.value.trim(),
+          aes_mode: el.aes_mode.value || 'cbc',
+          bark_options: barkOptions,
           emergency_keywords: keywordsRaw,
+          timezone: el.timezone.value.trim() || undefined,
+          quiet_hours: quietHours,
+          otp_patterns: otpPatterns,
+          notifiers: notifiers,
+          rules: rules,
           modem_device: el.modem_device.value.trim() || "/dev/ttyUSB2",
-          baud_rate: Number.isFinite(baud) && baud > 0 ? baud : 115200
-        };
+          baud_rate: Number.isFinite(baud) && baud > 0 ? baud : 115200,
+          poll_interval_secs: Number.isFinite(pollInterval) && pollInterval > 0 ? pollInterval : 60,
+          sms_mode: el.sms_mode.value || 'text'
+        });
       }
 
       function fillForm(cfg) {
-        el.bark_key.value = cfg.bark_key || '';
+        loaded = cfg;
+        el.bark_server.value = cfg.bark_server || 'https://api.day.app';
+        el.bark_key.value = (cfg.bark_key || []).map(function (d) { return d.key; }).join('\n');
         el.aes_key.value = cfg.aes_key || '';
-        el.aes_iv.value = cfg.aes_iv || '';
+        el.aes_mode.value = cfg.aes_mode || 'cbc';
+        el.bark_options.value = Object.keys(cfg.bark_options || {}).length ? JSON.stringify(cfg.bark_options, null, 2) : '';
         el.emergency_keywords.value = (cfg.emergency_keywords || []).join('\n');
+        el.timezone.value = cfg.timezone || '';
+        el.quiet_hours.value = (cfg.quiet_hours || []).length ? JSON.stringify(cfg.quiet_hours, null, 2) : '';
+        el.otp_patterns.value = (cfg.otp_patterns || []).join('\n');
+        el.notifiers.value = (cfg.notifiers || []).length ? JSON.stringify(cfg.notifiers, null, 2) : '';
+        el.rules.value = (cfg.rules || []).length ? JSON.stringify(cfg.rules, null, 2) : '';
         el.modem_device.value = cfg.modem_device || '/dev/ttyUSB2';
         el.baud_rate.value = cfg.baud_rate != null ? String(cfg.baud_rate) : '115200';
+        el.poll_interval_secs.value = cfg.poll_interval_secs != null ? String(cfg.poll_interval_secs) : '60';
+        el.sms_mode.value = cfg.sms_mode || 'text';
       }
 
       async function loadConfig() {
@@ -764,7 +1267,13 @@ const SETTINGS_HTML: &str = r#"<!DOCTYPE html>
       }
 
       async function saveConfig() {
-        const cfg = configFromForm();
+        let cfg;
+        try {
+          cfg = configFromForm();
+        } catch (e) {
+          setStatus('error', '配置有误：' + (e.message || e));
+          return;
+        }
         saveBtn.disabled = true;
         setStatus('', '保存中...');
         try {
@@ -776,7 +1285,8 @@ const SETTINGS_HTML: &str = r#"<!DOCTYPE html>
             body: JSON.stringify(cfg)
           });
           if (!res.ok) {
-            throw new Error('HTTP ' + res.status);
+            const detail = await res.text();
+            throw new Error(detail || 'HTTP ' + res.status);
           }
           setStatus('success', '保存成功（已写入 config.json）');
         } catch (e) {
@@ -800,3 +1310,555 @@ const SETTINGS_HTML: &str = r#"<!DOCTYPE html>
 </body>
 </html>
 "#;
+
+const HISTORY_HTML: &str = r#"<!DOCTYPE html>
+<html lang="zh-CN">
+<head>
+  <meta charset="UTF-8">
+  <title>SMS Forwarder - 短信历史</title>
+  <meta name="viewport" content="width=device-width,initial-scale=1">
+  <style>
+    :root {
+      color-scheme: light dark;
+      --bg: #000;
+      --fg: #fff;
+      --fg-muted: #aaa;
+      --border: #333;
+      --accent: #fff;
+      --error: #ff4d4f;
+      --success: #52c41a;
+      --warning: #faad14;
+    }
+    * {
+      box-sizing: border-box;
+    }
+    body {
+      margin: 0;
+      font-family: system-ui, -apple-system, BlinkMacSystemFont, "Segoe UI", sans-serif;
+      background-color: var(--bg);
+      color: var(--fg);
+      min-height: 100vh;
+      display: flex;
+      justify-content: center;
+      padding: 24px 12px;
+    }
+    .app {
+      width: 100%;
+      max-width: 960px;
+      padding: 24px 20px 20px;
+      border: 1px solid var(--border);
+      border-radius: 16px;
+      background: radial-gradient(circle at top left, #111 0, #000 40%);
+      box-shadow: 0 18px 45px rgba(0, 0, 0, 0.7);
+    }
+    .header {
+      display: flex;
+      align-items: center;
+      justify-content: space-between;
+      margin-bottom: 16px;
+    }
+    .title {
+      font-size: 20px;
+      font-weight: 600;
+      letter-spacing: .04em;
+      text-transform: uppercase;
+    }
+    .subtitle {
+      font-size: 12px;
+      color: var(--fg-muted);
+      margin-top: 4px;
+    }
+    .filters {
+      display: flex;
+      flex-wrap: wrap;
+      gap: 8px;
+      margin-bottom: 14px;
+    }
+    input[type="text"],
+    input[type="date"],
+    select {
+      border-radius: 10px;
+      border: 1px solid var(--border);
+      background: rgba(0, 0, 0, 0.9);
+      color: var(--fg);
+      padding: 8px 10px;
+      font-size: 13px;
+      outline: none;
+    }
+    input[type="text"] {
+      flex: 1;
+      min-width: 160px;
+    }
+    input:focus,
+    select:focus {
+      border-color: var(--accent);
+    }
+    .primary-btn {
+      border-radius: 999px;
+      border: 1px solid var(--fg);
+      background: var(--fg);
+      color: #000;
+      padding: 7px 16px;
+      font-size: 13px;
+      cursor: pointer;
+    }
+    .layout {
+      display: grid;
+      grid-template-columns: 280px 1fr;
+      gap: 12px;
+      min-height: 420px;
+    }
+    .panel {
+      border-radius: 12px;
+      border: 1px solid var(--border);
+      background: linear-gradient(135deg, #050505, #000);
+      overflow-y: auto;
+      max-height: 70vh;
+    }
+    .conv {
+      padding: 10px 12px;
+      border-bottom: 1px solid var(--border);
+      cursor: pointer;
+    }
+    .conv:hover,
+    .conv.active {
+      background: #111;
+    }
+    .conv-head {
+      display: flex;
+      justify-content: space-between;
+      font-size: 13px;
+      font-weight: 500;
+    }
+    .conv-meta,
+    .muted {
+      font-size: 11px;
+      color: var(--fg-muted);
+    }
+    .conv-body {
+      font-size: 12px;
+      color: var(--fg-muted);
+      white-space: nowrap;
+      overflow: hidden;
+      text-overflow: ellipsis;
+      margin-top: 4px;
+    }
+    .thread {
+      padding: 12px;
+      display: flex;
+      flex-direction: column;
+      gap: 10px;
+    }
+    .msg {
+      max-width: 80%;
+      border: 1px solid var(--border);
+      border-radius: 12px;
+      padding: 8px 10px;
+      font-size: 13px;
+      white-space: pre-wrap;
+      word-break: break-word;
+    }
+    .msg.outbound {
+      align-self: flex-end;
+      background: #1a1a1a;
+    }
+    .msg.inbound {
+      align-self: flex-start;
+    }
+    .msg .muted {
+      margin-top: 6px;
+      white-space: normal;
+    }
+    .badge {
+      display: inline-block;
+      border: 1px solid var(--border);
+      border-radius: 999px;
+      padding: 0 6px;
+      margin: 2px 4px 0 0;
+      font-size: 10px;
+    }
+    .badge.delivered,
+    .badge.sent {
+      border-color: var(--success);
+      color: var(--success);
+    }
+    .badge.pending,
+    .badge.queued {
+      border-color: var(--warning);
+      color: var(--warning);
+    }
+    .badge.expired,
+    .badge.failed,
+    .badge.dropped {
+      border-color: var(--error);
+      color: var(--error);
+    }
+    .empty {
+      padding: 20px;
+      text-align: center;
+      font-size: 12px;
+      color: var(--fg-muted);
+    }
+    .pager {
+      display: flex;
+      justify-content: space-between;
+      align-items: center;
+      margin-top: 12px;
+      font-size: 12px;
+      color: var(--fg-muted);
+    }
+    .link {
+      color: var(--fg);
+      text-decoration: none;
+      cursor: pointer;
+    }
+    .link[aria-disabled="true"] {
+      opacity: .3;
+      pointer-events: none;
+    }
+    @media (max-width: 720px) {
+      .layout {
+        grid-template-columns: 1fr;
+      }
+    }
+  </style>
+</head>
+<body>
+  <div class="app">
+    <div class="header">
+      <div>
+        <div class="title">SMS History</div>
+        <div class="subtitle">按号码归组的收发记录与转发状态</div>
+      </div>
+      <a class="link" href="/">返回发送短信</a>
+    </div>
+
+    <div class="filters">
+      <input id="q" type="text" placeholder="搜索正文或号码">
+      <select id="direction">
+        <option value="">全部</option>
+        <option value="inbound">收到</option>
+        <option value="outbound">发出</option>
+      </select>
+      <input id="from" type="date" title="起始日期">
+      <input id="to" type="date" title="结束日期">
+      <button class="primary-btn" id="searchBtn">搜索</button>
+    </div>
+
+    <div class="layout">
+      <div class="panel" id="convList"></div>
+      <div class="panel">
+        <div class="thread" id="thread"><div class="empty">选择左侧的号码查看记录</div></div>
+      </div>
+    </div>
+
+    <div class="pager">
+      <a class="link" id="prevBtn">上一页</a>
+      <span id="pageInfo"></span>
+      <a class="link" id="nextBtn">下一页</a>
+    </div>
+  </div>
+
+  <script>
+    (function () {
+      const PER_PAGE = 50;
+      const convList = document.getElementById('convList');
+      const thread = document.getElementById('thread');
+      const pageInfo = document.getElementById('pageInfo');
+      const prevBtn = document.getElementById('prevBtn');
+      const nextBtn = document.getElementById('nextBtn');
+      let page = 1;
+      let current = null;
+
+      function filters() {
+        const params = new URLSearchParams();
+        ['q', 'direction', 'from', 'to'].forEach(function (id) {
+          const v = document.getElementById(id).value.trim();
+          if (v) {
+            params.set(id, v);
+          }
+        });
+        return params;
+      }
+
+      function el(tag, cls, text) {
+        const e = document.createElement(tag);
+        if (cls) {
+          e.className = cls;
+        }
+        if (text != null) {
+          e.textContent = text;
+        }
+        return e;
+      }
+
+      function fmt(ts) {
+        return ts ? new Date(ts).toLocaleString() : '';
+      }
+
+      async function getJson(url) {
+        const res = await fetch(url);
+        if (!res.ok) {
+          throw new Error((await res.text()) || 'HTTP ' + res.status);
+        }
+        return res.json();
+      }
+
+      async function loadConversations() {
+        const params = filters();
+        params.set('page', page);
+        params.set('per_page', PER_PAGE);
+        convList.replaceChildren(el('div', 'empty', '加载中...'));
+        try {
+          const data = await getJson('/api/conversations?' + params);
+          convList.replaceChildren();
+          if (!data.items.length) {
+            convList.appendChild(el('div', 'empty', '没有记录'));
+          }
+          data.items.forEach(function (c) {
+            const item = el('div', 'conv' + (c.number === current ? ' active' : ''));
+            const head = el('div', 'conv-head');
+            head.appendChild(el('span', null, c.number || '(未知号码)'));
+            head.appendChild(el('span', 'conv-meta', c.count + ' 条'));
+            item.appendChild(head);
+            item.appendChild(el('div', 'conv-meta', fmt(c.last_at)));
+            item.appendChild(el('div', 'conv-body', (c.last_direction === 'outbound' ? '↗ ' : '') + c.last_body));
+            item.addEventListener('click', function () {
+              current = c.number;
+              Array.prototype.forEach.call(convList.children, function (n) { n.classList.remove('active'); });
+              item.classList.add('active');
+              loadThread();
+            });
+            convList.appendChild(item);
+          });
+          const pages = Math.max(1, Math.ceil(data.total / data.per_page));
+          pageInfo.textContent = '第 ' + data.page + ' / ' + pages + ' 页，共 ' + data.total + ' 个号码';
+          prevBtn.setAttribute('aria-disabled', data.page <= 1);
+          nextBtn.setAttribute('aria-disabled', data.page >= pages);
+        } catch (e) {
+          convList.replaceChildren(el('div', 'empty', '加载失败：' + (e.message || e)));
+        }
+      }
+
+      async function loadThread() {
+        const params = filters();
+        params.set('sender', current);
+        params.set('per_page', 200);
+        thread.replaceChildren(el('div', 'empty', '加载中...'));
+        try {
+          const data = await getJson('/api/messages?' + params);
+          thread.replaceChildren();
+          // 接口按时间倒序返回，会话中按时间正序显示
+          data.items.slice().reverse().forEach(function (m) {
+            const box = el('div', 'msg ' + m.direction, m.body);
+            const meta = el('div', 'muted');
+            meta.appendChild(el('span', null, fmt(m.sms_timestamp || m.at) + ' '));
+            if (m.direction === 'outbound') {
+              meta.appendChild(el('span', 'badge ' + m.status, m.status + (m.message_ref != null ? ' #' + m.message_ref : '')));
+              if (m.parts && m.parts.length > 1) {
+                m.parts.forEach(function (p) {
+                  const b = el('span', 'badge ' + p.status, p.seq + '/' + m.parts.length + ' ' + p.status);
+                  b.title = '#' + p.message_ref + ' ' + fmt(p.updated_at);
+                  meta.appendChild(b);
+                });
+              }
+              if (m.error) {
+                meta.appendChild(el('span', null, m.error));
+              }
+            } else if (m.dropped_by != null) {
+              meta.appendChild(el('span', 'badge dropped', '被规则丢弃：' + m.dropped_by));
+            } else {
+              (m.forwards || []).forEach(function (f) {
+                const b = el('span', 'badge ' + f.status, f.notifier + ' ' + f.status);
+                if (f.last_error) {
+                  b.title = f.last_error;
+                }
+                meta.appendChild(b);
+              });
+            }
+            box.appendChild(meta);
+            thread.appendChild(box);
+          });
+          if (data.total > data.items.length) {
+            thread.insertBefore(el('div', 'empty', '仅显示最近 ' + data.items.length + ' / ' + data.total + ' 条'), thread.firstChild);
+          }
+          if (!data.items.length) {
+            thread.appendChild(el('div', 'empty', '没有记录'));
+          }
+        } catch (e) {
+          thread.replaceChildren(el('div', 'empty', '加载失败：' + (e.message || e)));
+        }
+      }
+
+      document.getElementById('searchBtn').addEventListener('click', function () {
+        page = 1;
+        loadConversations();
+        if (current) {
+          loadThread();
+        }
+      });
+      document.getElementById('q').addEventListener('keydown', function (ev) {
+        if (ev.key === 'Enter') {
+          document.getElementById('searchBtn').click();
+        }
+      });
+      prevBtn.addEventListener('click', function () {
+        page = Math.max(1, page - 1);
+        loadConversations();
+      });
+      nextBtn.addEventListener('click', function () {
+        page += 1;
+        loadConversations();
+      });
+
+      loadConversations();
+    })();
+  </script>
+</body>
+</html>
+"#;
+
+const LOGIN_HTML: &str = r#"<!DOCTYPE html>
+<html lang="zh-CN">
+<head>
+  <meta charset="UTF-8">
+  <title>SMS Forwarder - 登录</title>
+  <meta name="viewport" content="width=device-width,initial-scale=1">
+  <style>
+    :root {
+      color-scheme: light dark;
+      --bg: #000;
+      --fg: #fff;
+      --fg-muted: #aaa;
+      --border: #333;
+      --accent: #fff;
+      --error: #ff4d4f;
+    }
+    * {
+      box-sizing: border-box;
+    }
+    body {
+      margin: 0;
+      font-family: system-ui, -apple-system, BlinkMacSystemFont, "Segoe UI", sans-serif;
+      background-color: var(--bg);
+      color: var(--fg);
+      min-height: 100vh;
+      display: flex;
+      align-items: center;
+      justify-content: center;
+      padding: 12px;
+    }
+    .app {
+      width: 100%;
+      max-width: 360px;
+      padding: 24px 20px 20px;
+      border: 1px solid var(--border);
+      border-radius: 16px;
+      background: radial-gradient(circle at top left, #111 0, #000 40%);
+      box-shadow: 0 18px 45px rgba(0, 0, 0, 0.7);
+    }
+    .title {
+      font-size: 20px;
+      font-weight: 600;
+      letter-spacing: .04em;
+      text-transform: uppercase;
+      margin-bottom: 20px;
+    }
+    .field {
+      display: flex;
+      flex-direction: column;
+      gap: 6px;
+      margin-bottom: 12px;
+    }
+    label {
+      font-size: 12px;
+      color: var(--fg-muted);
+    }
+    input {
+      width: 100%;
+      border-radius: 10px;
+      border: 1px solid var(--border);
+      background: rgba(0, 0, 0, 0.9);
+      color: var(--fg);
+      padding: 9px 10px;
+      font-size: 14px;
+      outline: none;
+    }
+    input:focus {
+      border-color: var(--accent);
+    }
+    .primary-btn {
+      width: 100%;
+      border-radius: 999px;
+      border: 1px solid var(--fg);
+      background: var(--fg);
+      color: #000;
+      padding: 8px 18px;
+      font-size: 14px;
+      font-weight: 500;
+      cursor: pointer;
+      margin-top: 6px;
+    }
+    .primary-btn[disabled] {
+      opacity: .5;
+      cursor: default;
+    }
+    .error {
+      min-height: 18px;
+      margin-top: 10px;
+      font-size: 12px;
+      color: var(--error);
+    }
+  </style>
+</head>
+<body>
+  <form class="app" id="loginForm">
+    <div class="title">SMS Forwarder</div>
+    <div class="field">
+      <label for="username">用户名</label>
+      <input id="username" type="text" autocomplete="username" required>
+    </div>
+    <div class="field">
+      <label for="password">密码</label>
+      <input id="password" type="password" autocomplete="current-password" required>
+    </div>
+    <button class="primary-btn" id="loginBtn" type="submit">登录</button>
+    <div class="error" id="error"></div>
+  </form>
+
+  <script>
+    (function () {
+      const form = document.getElementById('loginForm');
+      const btn = document.getElementById('loginBtn');
+      const error = document.getElementById('error');
+
+      form.addEventListener('submit', async function (ev) {
+        ev.preventDefault();
+        btn.disabled = true;
+        error.textContent = '';
+        try {
+          const res = await fetch('/login', {
+            method: 'POST',
+            headers: {
+              'Content-Type': 'application/json'
+            },
+            body: JSON.stringify({
+              username: document.getElementById('username').value.trim(),
+              password: document.getElementById('password').value
+            })
+          });
+          if (!res.ok) {
+            throw new Error((await res.text()) || 'HTTP ' + res.status);
+          }
+          window.location.href = '/';
+        } catch (e) {
+          error.textContent = '登录失败：' + (e.message || e);
+        } finally {
+          btn.disabled = false;
+        }
+      });
+    })();
+  </script>
+</body>
+</html>
+"#;
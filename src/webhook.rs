@@ -0,0 +1,207 @@
+// 通用 HTTP Webhook：按 JSON 模板生成请求体 POST 到指定 URL，可选 HMAC-SHA256 签名
+// 失败由发件箱按指数退避重试
+
+use async_trait::async_trait;
+use hmac::{Hmac, Mac};
+use serde_json::{json, Value};
+use sha2::Sha256;
+
+use crate::config::WebhookConfig;
+use crate::notify::{self, Notification, Notifier};
+
+/// 未配置模板时使用的请求体
+fn default_template() -> Value {
+    json!({
+        "sender": "{{sender}}",
+        "title": "{{title}}",
+        "body": "{{body}}",
+        "timestamp": "{{timestamp}}",
+        "index": "{{index}}",
+        "emergency": "{{emergency}}",
+    })
+}
+
+/// 占位符对应的取值
+fn variables(n: &Notification) -> [(&'static str, Value); 6] {
+    [
+        ("sender", json!(n.sender)),
+        ("title", json!(n.title)),
+        ("body", json!(n.body)),
+        ("timestamp", json!(n.timestamp.map(|t| t.to_rfc3339()))),
+        ("index", json!(n.indices.first())),
+        ("emergency", json!(n.emergency)),
+    ]
+}
+
+/// 递归替换模板中的占位符：整个字符串恰好是一个占位符时保留原类型（布尔、数字、null），
+/// 否则按文本插入
+fn render(template: &Value, vars: &[(&str, Value)]) -> Value {
+    match template {
+        Value::String(s) => {
+            for (name, value) in vars {
+                if s == &format!("{{{{{}}}}}", name) {
+                    return value.clone();
+                }
+            }
+            let mut out = s.clone();
+            for (name, value) in vars {
+                let text = match value {
+                    Value::String(v) => v.clone(),
+                    Value::Null => String::new(),
+                    other => other.to_string(),
+                };
+                out = out.replace(&format!("{{{{{}}}}}", name), &text);
+            }
+            Value::String(out)
+        }
+        Value::Array(items) => Value::Array(items.iter().map(|v| render(v, vars)).collect()),
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .map(|(k, v)| (k.clone(), render(v, vars)))
+                .collect(),
+        ),
+        other => other.clone(),
+    }
+}
+
+/// 对请求体计算 HMAC-SHA256，输出 "sha256=<hex>"
+fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(body);
+    let digest = mac.finalize().into_bytes();
+    let hex: String = digest.iter().map(|b| format!("{:02x}", b)).collect();
+    format!("sha256={}", hex)
+}
+
+/// Webhook 渠道
+pub struct Webhook {
+    cfg: WebhookConfig,
+}
+
+impl Webhook {
+    pub fn new(cfg: WebhookConfig) -> Self {
+        Self { cfg }
+    }
+}
+
+#[async_trait]
+impl Notifier for Webhook {
+    async fn send(&self, n: &Notification) -> anyhow::Result<()> {
+        let template = self.cfg.template.clone().unwrap_or_else(default_template);
+        let body = serde_json::to_vec(&render(&template, &variables(n)))?;
+
+        let mut req = notify::http_client()
+            .post(&self.cfg.url)
+            .header("Content-Type", "application/json");
+        for (k, v) in &self.cfg.headers {
+            req = req.header(k, v);
+        }
+        if !self.cfg.secret.is_empty() {
+            req = req.header(&self.cfg.signature_header, sign(&self.cfg.secret, &body));
+        }
+
+        tracing::debug!(url = %self.cfg.url, "posting webhook");
+        let res = req.body(body).send().await?;
+        tracing::debug!(status = %res.status(), "webhook request completed");
+        res.error_for_status()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::DateTime;
+
+    use crate::config::BarkOptions;
+    use crate::notify::Level;
+
+    fn notification() -> Notification {
+        Notification {
+            title: "SMS from 10086".to_string(),
+            body: "验证码 123456".to_string(),
+            emergency: true,
+            level: Level::Critical,
+            sender: "10086".to_string(),
+            timestamp: Some(DateTime::parse_from_rfc3339("2024-05-06T12:34:56+08:00").unwrap()),
+            indices: vec![3, 4],
+            bark: BarkOptions::default(),
+            history_id: None,
+        }
+    }
+
+    #[test]
+    fn sign_known_answers() {
+        // RFC 4231 测试用例 2
+        assert_eq!(
+            sign("Jefe", b"what do ya want for nothing?"),
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+        // GitHub Webhook 文档中的示例
+        assert_eq!(
+            sign("It's a Secret to Everybody", b"Hello, World!"),
+            "sha256=757107ea0eb2509fc211221cce984b8a37570b6d7586c22c46f4379c8b043e17"
+        );
+    }
+
+    #[test]
+    fn default_template_keeps_types() {
+        let body = render(&default_template(), &variables(&notification()));
+        assert_eq!(
+            body,
+            json!({
+                "sender": "10086",
+                "title": "SMS from 10086",
+                "body": "验证码 123456",
+                "timestamp": "2024-05-06T12:34:56+08:00",
+                "index": 3,
+                "emergency": true,
+            })
+        );
+    }
+
+    #[test]
+    fn whole_string_placeholder_keeps_type() {
+        let mut n = notification();
+        n.timestamp = None;
+        n.indices.clear();
+        let body = render(
+            &json!({"e": "{{emergency}}", "i": "{{index}}", "t": "{{timestamp}}", "n": 1, "x": false}),
+            &variables(&n),
+        );
+        assert_eq!(body, json!({"e": true, "i": null, "t": null, "n": 1, "x": false}));
+    }
+
+    #[test]
+    fn embedded_placeholders_are_text() {
+        let body = render(
+            &json!("[{{sender}}] {{body}} ({{emergency}}, #{{index}}) {{unknown}}"),
+            &variables(&notification()),
+        );
+        assert_eq!(body, json!("[10086] 验证码 123456 (true, #3) {{unknown}}"));
+
+        // null 按空串插入
+        let mut n = notification();
+        n.timestamp = None;
+        assert_eq!(render(&json!("at {{timestamp}}."), &variables(&n)), json!("at ."));
+    }
+
+    #[test]
+    fn nested_objects_and_arrays() {
+        let template = json!({
+            "msgtype": "text",
+            "text": {"content": "{{title}}\n{{body}}", "mentioned": ["{{sender}}", "@all"]},
+            "meta": {"deep": {"emergency": "{{emergency}}"}},
+        });
+        let body = render(&template, &variables(&notification()));
+        assert_eq!(
+            body,
+            json!({
+                "msgtype": "text",
+                "text": {"content": "SMS from 10086\n验证码 123456", "mentioned": ["10086", "@all"]},
+                "meta": {"deep": {"emergency": true}},
+            })
+        );
+    }
+}